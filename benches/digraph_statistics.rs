@@ -0,0 +1,38 @@
+#[macro_use]
+extern crate criterion;
+extern crate keynome;
+
+use criterion::{black_box, Criterion};
+use keynome::{KeyEvent, KeystrokeLogger};
+
+fn build_logger(n_events: usize, suffix: Option<char>) -> KeystrokeLogger {
+    let text = "the quick brown fox jumps over the lazy dog";
+    let keys: Vec<char> = text.chars().filter(|c| c.is_ascii_lowercase()).collect();
+
+    let mut kstr = KeystrokeLogger::new();
+    let mut ts = 0;
+    for i in 0..n_events {
+        kstr.add_key_event(KeyEvent::new(ts, keys[i % keys.len()]));
+        ts += 80 + (i as u128 * 37) % 120;
+    }
+    // a single non-letter key forces the general hashed path over the same window
+    if let Some(c) = suffix {
+        kstr.add_key_event(KeyEvent::new(ts, c));
+    }
+    kstr
+}
+
+fn bench_digraph_statistics(c: &mut Criterion) {
+    for n_events in &[100, 300, 1000] {
+        let letters = build_logger(*n_events, None);
+        let mixed = build_logger(*n_events, Some('!'));
+
+        c.bench_function(&format!("digraph_statistics_letters_{}", n_events),
+                         |b| b.iter(|| black_box(letters.compute_digraph_statistics())));
+        c.bench_function(&format!("digraph_statistics_hashed_{}", n_events),
+                         |b| b.iter(|| black_box(mixed.compute_digraph_statistics())));
+    }
+}
+
+criterion_group!(benches, bench_digraph_statistics);
+criterion_main!(benches);
@@ -1,5 +1,8 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code, unused_imports)]
 
+extern crate alloc;
+
 #[macro_export]
 macro_rules! assert_numerically_similar {
     ( $eps:expr, $x:expr, $y:expr ) => {
@@ -7,444 +10,5953 @@ macro_rules! assert_numerically_similar {
     };
 }
 
-use std::time::{SystemTime, Duration, UNIX_EPOCH};
-use std::{thread, time};
-use std::collections::{HashMap, VecDeque};
-use std::fmt::Display;
-use std::hash::Hash;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+#[cfg(not(feature = "std"))]
+use hashbrown::{HashMap, HashSet};
+#[cfg(not(feature = "std"))]
+use alloc::{borrow::ToOwned, boxed::Box, format, string::{String, ToString}, vec, vec::Vec};
+use alloc::borrow::Cow;
+use alloc::collections::{BTreeMap, VecDeque};
+use core::fmt;
+use core::fmt::Display;
+use core::hash::Hash;
 
-extern crate statistical;
 extern crate serde;
-use serde::{Serialize, Serializer, Deserialize};
+#[cfg(feature = "std")]
+extern crate flate2;
+#[cfg(feature = "std")]
+use flate2::Compression;
+#[cfg(feature = "std")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "std")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+#[cfg(feature = "encryption")]
+use chacha20poly1305::aead::Aead;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error as _;
+
+// the f64 methods of std, backed by libm in the no_std core
+#[cfg(not(feature = "std"))]
+trait FloatExt {
+    fn sqrt(self) -> f64;
+    fn powi(self, n: i32) -> f64;
+    fn ln(self) -> f64;
+    fn cos(self) -> f64;
+    fn floor(self) -> f64;
+    fn ceil(self) -> f64;
+    fn round(self) -> f64;
+}
+
+#[cfg(not(feature = "std"))]
+impl FloatExt for f64 {
+    fn sqrt(self) -> f64 {
+        libm::sqrt(self)
+    }
+
+    fn powi(self, n: i32) -> f64 {
+        libm::pow(self, n as f64)
+    }
+
+    fn ln(self) -> f64 {
+        libm::log(self)
+    }
+
+    fn cos(self) -> f64 {
+        libm::cos(self)
+    }
+
+    fn floor(self) -> f64 {
+        libm::floor(self)
+    }
+
+    fn ceil(self) -> f64 {
+        libm::ceil(self)
+    }
+
+    fn round(self) -> f64 {
+        libm::round(self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeynomeError {
+    InvalidProfile(String),
+    Io(String),
+    Parse(String),
+    // wrong passphrase, tampered or truncated encrypted profile
+    Decryption(String),
+}
+
+impl fmt::Display for KeynomeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeynomeError::InvalidProfile(msg) => write!(f, "invalid profile: {}", msg),
+            KeynomeError::Io(msg) => write!(f, "io error: {}", msg),
+            KeynomeError::Parse(msg) => write!(f, "parse error: {}", msg),
+            KeynomeError::Decryption(msg) => write!(f, "decryption error: {}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for KeynomeError {}
+
+// why a serialized profile couldn't be read
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileError {
+    // not JSON, or a value of the wrong shape
+    Json(String),
+    MissingField(String),
+    // field name and the offending value
+    InvalidNumber(String, String),
+    InvalidDigraphKey(String),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileError::Json(msg) => write!(f, "malformed JSON: {}", msg),
+            ProfileError::MissingField(name) => write!(f, "missing field {}", name),
+            ProfileError::InvalidNumber(name, value) => write!(f, "{} is not a number: {}", name, value),
+            ProfileError::InvalidDigraphKey(key) => write!(f, "invalid digraph key: {:?}", key),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ProfileError {}
 
+impl From<serde_json::Error> for ProfileError {
+    fn from(e: serde_json::Error) -> Self {
+        ProfileError::Json(e.to_string())
+    }
+}
+
+impl From<ProfileError> for KeynomeError {
+    fn from(e: ProfileError) -> Self {
+        KeynomeError::Parse(e.to_string())
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct KeyEvent {
-    timestamp_ms: u128,
+    // microseconds, so that fast digraphs aren't quantized to 0ms; intervals are still
+    // reported in (fractional) milliseconds
+    timestamp_us: u128,
     key: char,
+    // analog pressure, only reported by some input devices
+    pressure: Option<f32>,
+    // time the key was released, when the input source reports it
+    release_us: Option<u128>,
+    // no digraph is formed with the preceding event
+    follows_break: bool,
+}
+
+impl KeyEvent {
+    pub fn new(timestamp_ms: u128, key: char) -> KeyEvent {
+        KeyEvent::from_micros(timestamp_ms * 1000, key)
+    }
+
+    pub fn from_micros(timestamp_us: u128, key: char) -> KeyEvent {
+        KeyEvent { timestamp_us, key, pressure: None, release_us: None, follows_break: false }
+    }
+
+    pub fn with_release(self, release_ms: u128) -> KeyEvent {
+        self.with_release_us(release_ms * 1000)
+    }
+
+    pub fn with_release_us(mut self, release_us: u128) -> KeyEvent {
+        self.release_us = Some(release_us);
+        self
+    }
+
+    pub fn with_pressure(mut self, pressure: f32) -> KeyEvent {
+        self.pressure = Some(pressure);
+        self
+    }
+
+    pub fn timestamp_ms(&self) -> u128 {
+        self.timestamp_us / 1000
+    }
+
+    pub fn timestamp_us(&self) -> u128 {
+        self.timestamp_us
+    }
+
+    pub fn key(&self) -> char {
+        self.key
+    }
+
+    pub fn pressure(&self) -> Option<f32> {
+        self.pressure
+    }
+
+    pub fn release_ms(&self) -> Option<u128> {
+        self.release_us.map(|us| us / 1000)
+    }
+
+    pub fn release_us(&self) -> Option<u128> {
+        self.release_us
+    }
+
+    // rollover: `next` is pressed before this key is released; unknown without a release time
+    pub fn is_rolled_over_by(&self, next: &KeyEvent) -> Option<bool> {
+        self.release_us.map(|release| next.timestamp_us < release)
+    }
+}
+
+// non-negative interval in ms between two microsecond timestamps; out-of-order
+// timestamps (possible under rollover) count as simultaneous
+fn span_ms(from_us: u128, to_us: u128) -> f64 {
+    to_us.saturating_sub(from_us) as f64 / 1000.0
+}
+
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// splits CSV text into records, quoted fields may contain separators and newlines
+fn parse_csv_records(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); field.push('"'); },
+            '"' => { quoted = !quoted; },
+            ',' if !quoted => { fields.push(core::mem::take(&mut field)); },
+            '\r' if !quoted => {},
+            '\n' if !quoted => {
+                fields.push(core::mem::take(&mut field));
+                records.push(core::mem::take(&mut fields));
+            },
+            _ => { field.push(c); },
+        }
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push(fields);
+    }
+    records
 }
 
 pub type Digraph = (char, char);
+pub type Trigraph = (char, char, char);
+
+// frequent english trigraphs, enough samples accumulate for them in a typical enrollment
+pub const COMMON_TRIGRAPHS: [&str; 16] = [
+    "the", "and", "ing", "ion", "tio", "ent", "ere", "her",
+    "ate", "ver", "ter", "tha", "ati", "for", "his", "con",
+];
+
+pub fn trigraph_allowlist(trigraphs: &[&str]) -> HashSet<Trigraph> {
+    trigraphs.iter()
+        .filter_map(|t| match t.chars().collect::<Vec<char>>()[..] {
+            [a, b, c] => Some((a, b, c)),
+            _ => None,
+        })
+        .collect()
+}
+
+// interval between two consecutive keys, from press (Down) or release (Up) of the first
+// to press or release of the second; only DownDown is available without release times
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimingFeature {
+    DownDown,
+    DownUp,
+    UpDown,
+    UpUp,
+}
+
+impl TimingFeature {
+    pub const ALL: [TimingFeature; 4] =
+        [TimingFeature::DownDown, TimingFeature::DownUp, TimingFeature::UpDown, TimingFeature::UpUp];
+
+    // signed, since UpDown is negative when keys overlap
+    fn interval(&self, ev1: &KeyEvent, ev2: &KeyEvent) -> Option<f64> {
+        let (t1, t2) = match self {
+            TimingFeature::DownDown => (ev1.timestamp_us, ev2.timestamp_us),
+            TimingFeature::DownUp => (ev1.timestamp_us, ev2.release_us?),
+            TimingFeature::UpDown => (ev1.release_us?, ev2.timestamp_us),
+            TimingFeature::UpUp => (ev1.release_us?, ev2.release_us?),
+        };
+        Some((t2 as f64 - t1 as f64) / 1000.0)
+    }
+}
+
+// "12.345" (ms) -> 12345 (us), at most microsecond precision
+fn parse_ms_as_us(s: &str) -> Option<u128> {
+    let (int, frac) = match s.split_once('.') {
+        Some((int, frac)) => (int, frac),
+        None => (s, ""),
+    };
+    if frac.len() > 3 || !frac.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let ms: u128 = int.parse().ok()?;
+    let frac_us: u128 = if frac.is_empty() { 0 } else { format!("{:0<3}", frac).parse().ok()? };
+    Some(ms * 1000 + frac_us)
+}
+
+fn format_digraph_key(k: &Digraph) -> String {
+    format!("{}-{}", k.0, k.1)
+}
+
+fn parse_digraph_key(s: &str) -> Option<Digraph> {
+    let mut chars = s.chars();
+    let k1 = chars.next()?;
+    if chars.next()? != '-' {
+        return None;
+    }
+    let k2 = chars.next()?;
+    if chars.next().is_some() {
+        return None;
+    }
+    Some((k1, k2))
+}
+
+// (de)serializes digraph-keyed weights using the same "a-b" keys as the stats table
+mod serde_digraph_weights {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(weights: &Option<HashMap<Digraph, f64>>, serializer: S)
+        -> Result<S::Ok, S::Error> {
+        let str_keyed_map: Option<HashMap<String, f64>> = weights.as_ref()
+            .map(|w| w.iter().map(|(k, v)| (format_digraph_key(k), *v)).collect());
+        str_keyed_map.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D)
+        -> Result<Option<HashMap<Digraph, f64>>, D::Error> {
+        let str_keyed_map: Option<HashMap<String, f64>> = Option::deserialize(deserializer)?;
+        match str_keyed_map {
+            Some(m) => {
+                let mut weights = HashMap::new();
+                for (k, v) in m {
+                    let digraph = parse_digraph_key(&k)
+                        .ok_or_else(|| D::Error::custom(format!("invalid digraph key: {:?}", k)))?;
+                    weights.insert(digraph, v);
+                }
+                Ok(Some(weights))
+            },
+            None => Ok(None),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DigraphStats {
     pub size_samples: usize,
     pub mean: f64,
     pub std: f64,
+    // NaN when not present, e.g. in v1 profiles which only stored mean/std
+    #[serde(default = "not_present", skip_serializing_if = "is_not_present")]
+    pub median: f64,
+    #[serde(default = "not_present", skip_serializing_if = "is_not_present")]
+    pub iqr: f64,
+    // observed range, NaN when not present like median and iqr
+    #[serde(default = "not_present", skip_serializing_if = "is_not_present")]
+    pub min: f64,
+    #[serde(default = "not_present", skip_serializing_if = "is_not_present")]
+    pub max: f64,
+    // timestamp of the last observation folded in, None when unknown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_updated_ms: Option<u64>,
+}
+
+fn not_present() -> f64 {
+    f64::NAN
+}
+
+fn is_not_present(v: &f64) -> bool {
+    v.is_nan()
+}
+
+impl DigraphStats {
+    pub fn new(size_samples: usize, mean: f64, std: f64) -> DigraphStats {
+        DigraphStats {
+            size_samples, mean, std,
+            median: not_present(), iqr: not_present(), min: not_present(), max: not_present(), last_updated_ms: None,
+        }
+    }
+
+    pub fn has_quantiles(&self) -> bool {
+        !self.median.is_nan() && !self.iqr.is_nan()
+    }
+
+    pub fn has_range(&self) -> bool {
+        !self.min.is_nan() && !self.max.is_nan()
+    }
+
+    // widens the range to cover `other`'s, unless either side lacks one
+    fn extend_range(&mut self, other: &DigraphStats) {
+        if self.has_range() && other.has_range() {
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        } else {
+            self.min = not_present();
+            self.max = not_present();
+        }
+    }
+
+    // median and iqr in place of mean and std, the iqr scaled to a normal std (iqr = 1.349 std)
+    fn robust(&self) -> DigraphStats {
+        DigraphStats { mean: self.median, std: self.iqr / 1.349, ..self.clone() }
+    }
+
+    // distance of `other` (the sample) from these (profile) statistics
+    pub fn distance(&self, other: &DigraphStats, metric: DistanceMetric) -> f64 {
+        let delta = (self.mean - other.mean).abs();
+        match metric {
+            DistanceMetric::Absolute => delta,
+            DistanceMetric::Scaled => delta / (0.001 + self.std),
+            DistanceMetric::ZScore => delta / self.std.max(MIN_STD_MS),
+            DistanceMetric::Squared => delta * delta,
+            DistanceMetric::PooledZScore => delta / self.pooled_std(other).max(MIN_STD_MS),
+            DistanceMetric::Mahalanobis => {
+                let std = self.std.max(MIN_STD_MS);
+                ((delta / std).powi(2) + ((self.std - other.std) / std).powi(2)).sqrt()
+            },
+        }
+    }
+
+    // std of both samples around their own means, weighted by degrees of freedom
+    fn pooled_std(&self, other: &DigraphStats) -> f64 {
+        let dof = (self.size_samples + other.size_samples).saturating_sub(2);
+        if dof == 0 {
+            return ((self.std.powi(2) + other.std.powi(2)) / 2.0).sqrt();
+        }
+        let ss = self.std.powi(2) * self.size_samples.saturating_sub(1) as f64
+            + other.std.powi(2) * other.size_samples.saturating_sub(1) as f64;
+        (ss / dof as f64).sqrt()
+    }
+
+    // samples needed for the z-confidence interval of the mean (2 * z * std / sqrt(n))
+    // to be at most `target_ci_width_ms` wide, given the observed std
+    pub fn required_samples(&self, target_ci_width_ms: f64, z: f64) -> usize {
+        if target_ci_width_ms <= 0.0 || target_ci_width_ms.is_nan() {
+            return usize::MAX;
+        }
+        let n = (2.0 * z * self.std / target_ci_width_ms).powi(2).ceil();
+        (n as usize).max(2)
+    }
+
+    // pooled statistics of both sample sets from the summaries alone. Mean and std come out
+    // as if computed over the concatenated samples (up to rounding); median and iqr can't be
+    // pooled this way and are dropped, see merge_digraph_statistics for the exact merge.
+    pub fn combine(&self, other: &DigraphStats) -> DigraphStats {
+        if self.size_samples == 0 || other.size_samples == 0 {
+            return if self.size_samples == 0 { other.clone() } else { self.clone() };
+        }
+        let n = self.size_samples + other.size_samples;
+        let (na, nb) = (self.size_samples as f64, other.size_samples as f64);
+        let delta = other.mean - self.mean;
+        let mean = self.mean + delta * nb / n as f64;
+        let m2 = self.std.powi(2) * (na - 1.0) + other.std.powi(2) * (nb - 1.0) + delta.powi(2) * na * nb / n as f64;
+
+        let mut combined = DigraphStats::new(n, mean, (m2 / (n - 1) as f64).sqrt());
+        combined.min = self.min;
+        combined.max = self.max;
+        combined.extend_range(other);
+        combined.last_updated_ms = self.last_updated_ms.max(other.last_updated_ms);
+        combined
+    }
+}
+
+// merges two stats maps. Digraphs whose raw samples are given on both sides are recomputed
+// exactly from the concatenated samples; all others fall back to DigraphStats::combine.
+pub fn merge_digraph_statistics(
+    stats_a: &HashMap<Digraph, DigraphStats>, stats_b: &HashMap<Digraph, DigraphStats>,
+    samples_a: Option<&HashMap<Digraph, Vec<f64>>>, samples_b: Option<&HashMap<Digraph, Vec<f64>>>)
+    -> HashMap<Digraph, DigraphStats> {
+    let mut merged = stats_a.clone();
+    for (k, vb) in stats_b.iter() {
+        let raw = match (samples_a.and_then(|s| s.get(k)), samples_b.and_then(|s| s.get(k))) {
+            (Some(a), Some(b)) if stats_a.contains_key(k) => summarize(&[a.as_slice(), b.as_slice()].concat()),
+            _ => None,
+        };
+        let v = match (raw, merged.get(k)) {
+            (Some(mut exact), Some(va)) => {
+                exact.last_updated_ms = va.last_updated_ms.max(vb.last_updated_ms);
+                exact
+            },
+            (_, Some(va)) => va.combine(vb),
+            (_, None) => vb.clone(),
+        };
+        merged.insert(*k, v);
+    }
+    merged
+}
+
+// outlier trimming applied to each digraph's intervals before summarizing
+#[derive(Clone, Debug, Default)]
+pub struct StatisticsOptions {
+    // intervals above this are dropped, e.g. pauses to think
+    pub max_interval_ms: Option<u128>,
+    // intervals further than this many stds from the mean are dropped, in a single pass
+    // after the ceiling
+    pub trim_sigma: Option<f64>,
+}
+
+impl StatisticsOptions {
+    fn trim(&self, v: &[f64]) -> Vec<f64> {
+        let mut kept: Vec<f64> = match self.max_interval_ms {
+            Some(max_ms) => v.iter().cloned().filter(|x| *x <= max_ms as f64).collect(),
+            None => v.to_vec(),
+        };
+        if let (Some(k), Some(stats)) = (self.trim_sigma, summarize(&kept)) {
+            kept.retain(|x| (x - stats.mean).abs() <= k * stats.std);
+        }
+        kept
+    }
+}
+
+// windows up to this many events over lowercase letters skip hashing, see benches/
+const SMALL_SAMPLE_LIMIT: usize = 1024;
+
+fn mean(v: &[f64]) -> f64 {
+    v.iter().fold(0.0, |acc, x| acc + x) / v.len() as f64
+}
+
+// sample standard deviation (n - 1 denominator), v needs at least two values
+fn standard_deviation(v: &[f64], mean: f64) -> f64 {
+    let sum_sq = v.iter().map(|x| (x - mean) * (x - mean)).fold(0.0, |acc, x| acc + x);
+    (sum_sq / (v.len() - 1) as f64).sqrt()
+}
+
+// None for degenerate samples (fewer than two finite values), which have no sample std
+fn summarize(v: &[f64]) -> Option<DigraphStats> {
+    if v.iter().any(|x| !x.is_finite()) {
+        let finite: Vec<f64> = v.iter().cloned().filter(|x| x.is_finite()).collect();
+        return summarize(&finite);
+    }
+    if v.len() < 2 {
+        return None;
+    }
+    let mean = mean(v);
+    let std = standard_deviation(v, mean);
+    let mut stats = DigraphStats::new(v.len(), mean, std);
+
+    let mut sorted = v.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    stats.median = quantile(&sorted, 0.5);
+    stats.iqr = quantile(&sorted, 0.75) - quantile(&sorted, 0.25);
+    stats.min = sorted[0];
+    stats.max = sorted[sorted.len() - 1];
+    Some(stats)
+}
+
+// linear interpolation between the closest ranks of a sorted, non-empty sample
+fn quantile(sorted: &[f64], p: f64) -> f64 {
+    let h = (sorted.len() - 1) as f64 * p;
+    let (lo, hi) = (h.floor() as usize, h.ceil() as usize);
+    sorted[lo] + (h - lo as f64) * (sorted[hi] - sorted[lo])
+}
+
+// summarizes every key backed by at least two samples
+fn summarize_samples<K: Clone + Eq + Hash>(samples: &HashMap<K, Vec<f64>>) -> HashMap<K, DigraphStats> {
+    let mut stats: HashMap<K, DigraphStats> = HashMap::new();
+    for (k, v) in samples.iter() {
+        if let Some(digraph_stats) = summarize(v) {
+            stats.insert(k.clone(), digraph_stats);
+        }
+    }
+    stats
+}
+
+// approximate median and IQR in O(1) memory with three P-square estimators. Exact up to
+// 5 samples; beyond that the estimates typically stay within a few percent of the
+// interquartile range for unimodal timing distributions, but are less reliable for
+// small or strongly multimodal samples.
+#[derive(Clone, Debug)]
+pub struct QuantileSketch {
+    q1: P2Quantile,
+    median: P2Quantile,
+    q3: P2Quantile,
+}
+
+impl Default for QuantileSketch {
+    fn default() -> Self {
+        QuantileSketch { q1: P2Quantile::new(0.25), median: P2Quantile::new(0.5), q3: P2Quantile::new(0.75) }
+    }
+}
+
+impl QuantileSketch {
+    pub fn new() -> QuantileSketch {
+        QuantileSketch::default()
+    }
+
+    pub fn observe(&mut self, x: f64) {
+        self.q1.observe(x);
+        self.median.observe(x);
+        self.q3.observe(x);
+    }
+
+    pub fn median(&self) -> Option<f64> {
+        self.median.estimate()
+    }
+
+    pub fn iqr(&self) -> Option<f64> {
+        Some(self.q3.estimate()? - self.q1.estimate()?)
+    }
+}
+
+// online mean/variance (Welford) and approximate quantiles, matching summarize()
+// without keeping the samples
+#[derive(Clone, Debug, Default)]
+pub struct RunningStats {
+    n: usize,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+    quantiles: QuantileSketch,
+}
+
+impl RunningStats {
+    pub fn new() -> RunningStats {
+        RunningStats::default()
+    }
+
+    pub fn push(&mut self, x: f64) {
+        if !x.is_finite() {
+            return;
+        }
+        self.n += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (x - self.mean);
+        self.quantiles.observe(x);
+        if self.n == 1 {
+            self.min = x;
+            self.max = x;
+        } else {
+            self.min = self.min.min(x);
+            self.max = self.max.max(x);
+        }
+    }
+
+    pub fn to_stats(&self) -> Option<DigraphStats> {
+        if self.n < 2 {
+            return None;
+        }
+        let mut stats = DigraphStats::new(self.n, self.mean, (self.m2 / (self.n - 1) as f64).sqrt());
+        stats.median = self.quantiles.median().unwrap_or(stats.median);
+        stats.iqr = self.quantiles.iqr().unwrap_or(stats.iqr);
+        stats.min = self.min;
+        stats.max = self.max;
+        Some(stats)
+    }
+}
+
+// exact sums of integer microsecond intervals, which unlike RunningStats can also remove
+// a value again without accumulating rounding error. The intervals are counted too, so the
+// range survives removing its minimum or maximum.
+#[derive(Clone, Debug, Default)]
+struct IntervalSums {
+    n: u128,
+    sum_us: u128,
+    sum_sq_us: u128,
+    counts: BTreeMap<u128, usize>,
+}
+
+impl IntervalSums {
+    fn add(&mut self, interval_us: u128) {
+        self.n += 1;
+        self.sum_us += interval_us;
+        self.sum_sq_us += interval_us * interval_us;
+        *self.counts.entry(interval_us).or_default() += 1;
+    }
+
+    fn remove(&mut self, interval_us: u128) {
+        self.n -= 1;
+        self.sum_us -= interval_us;
+        self.sum_sq_us -= interval_us * interval_us;
+        if let Some(count) = self.counts.get_mut(&interval_us) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&interval_us);
+            }
+        }
+    }
+
+    // mean, std and range, no quantiles
+    fn to_stats(&self) -> Option<DigraphStats> {
+        if self.n < 2 {
+            return None;
+        }
+        let n = self.n as f64;
+        let var_us = (self.n * self.sum_sq_us - self.sum_us * self.sum_us) as f64 / (n * (n - 1.0));
+        let mut stats = DigraphStats::new(self.n as usize, self.sum_us as f64 / n / 1000.0, var_us.sqrt() / 1000.0);
+        if let (Some((min_us, _)), Some((max_us, _))) = (self.counts.first_key_value(), self.counts.last_key_value()) {
+            stats.min = *min_us as f64 / 1000.0;
+            stats.max = *max_us as f64 / 1000.0;
+        }
+        Some(stats)
+    }
+}
+
+pub const BACKSPACE: char = '\u{8}';
+
+pub trait Clock {
+    fn now_ms(&self) -> u128;
+
+    // clocks with a finer resolution should override this
+    fn now_us(&self) -> u128 {
+        self.now_ms() * 1000
+    }
+}
+
+#[cfg(feature = "std")]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u128 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()
+    }
+
+    fn now_us(&self) -> u128 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros()
+    }
+}
+
+// for loggers that only replay recorded events and never call add_keystroke
+struct NoClock;
+
+impl Clock for NoClock {
+    fn now_ms(&self) -> u128 {
+        0
+    }
 }
 
 pub struct KeystrokeLogger {
+    clock: Box<dyn Clock>,
     events: VecDeque<KeyEvent>,
     events_limit: Option<usize>,
+    // events older than this many ms before the latest one are evicted
+    time_window_ms: Option<u128>,
+    // maps produced characters to the physical key, e.g. '!' -> '1'
+    key_normalizer: Option<HashMap<char, char>>,
+    pending_break: bool,
+    // summary-only mode: digraphs folded in as they complete, only the last two events are kept
+    summary: Option<HashMap<Digraph, RunningStats>>,
+    // plausible [min, max] hold duration in ms, dwell samples outside are discarded
+    dwell_band: Option<(f64, f64)>,
+    // digraphs whose first event fell out of the events_limit buffer, see retain_evicted_digraphs
+    evicted: Option<HashMap<Digraph, RunningStats>>,
+    // key of the last evicted event, for the post-correction check of the next evicted digraph
+    evicted_key: Option<char>,
+    // leave out the first digraph typed after a backspace
+    skip_post_correction: bool,
+    // per-digraph sums of the buffered pairs, kept up to date as events come and go
+    running: Option<HashMap<Digraph, IntervalSums>>,
+}
+
+#[cfg(feature = "std")]
+impl Default for KeystrokeLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// (key, timestamp_ms) pairs from any source: a file, a channel, a test vector
+impl Extend<(char, u128)> for KeystrokeLogger {
+    fn extend<I: IntoIterator<Item = (char, u128)>>(&mut self, iter: I) {
+        for (key, timestamp_ms) in iter {
+            self.add_key_event(KeyEvent::new(timestamp_ms, key));
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::iter::FromIterator<(char, u128)> for KeystrokeLogger {
+    fn from_iter<I: IntoIterator<Item = (char, u128)>>(iter: I) -> Self {
+        let mut kstr = KeystrokeLogger::new();
+        kstr.extend(iter);
+        kstr
+    }
 }
 
 impl KeystrokeLogger {
+    #[cfg(feature = "std")]
     pub fn new() -> KeystrokeLogger {
-        KeystrokeLogger { events: VecDeque::new(), events_limit: None }
+        KeystrokeLogger::with_clock(Box::new(SystemClock))
     }
 
-    pub fn add_key_event(&mut self, ev: KeyEvent) {
-        self.events.push_back(ev);
-        if let Some(limit) = self.events_limit {
-            if self.events.len() > limit {
-                for _ in 0..self.events.len()-limit {
-                    self.events.pop_front();
-                }
-            }
+    fn detached() -> KeystrokeLogger {
+        KeystrokeLogger::with_clock(Box::new(NoClock))
+    }
+
+    // timestamps of add_keystroke are taken from the given clock
+    pub fn with_clock(clock: Box<dyn Clock>) -> KeystrokeLogger {
+        KeystrokeLogger {
+            clock, events: VecDeque::new(), events_limit: None, time_window_ms: None, key_normalizer: None, pending_break: false, summary: None,
+            dwell_band: None, evicted: None, evicted_key: None, skip_post_correction: false, running: None,
         }
     }
 
-    pub fn add_keystroke(&mut self, key: char) {
-        let now = SystemTime::now();
-        let ts = now.duration_since(UNIX_EPOCH).unwrap().as_millis();
-        self.add_key_event(KeyEvent { timestamp_ms: ts, key });
+    // maintains the digraph sums on every insertion and eviction, so compute_digraph_statistics
+    // takes O(distinct digraphs) instead of a pass over the buffer; the statistics then come
+    // without median and iqr
+    pub fn set_incremental(&mut self, incremental: bool) {
+        self.running = None;
+        if incremental {
+            self.rebuild_running();
+        }
     }
 
-    pub fn set_events_limit(&mut self, limit: usize) {
-        self.events_limit = Some(limit);
+    fn rebuild_running(&mut self) {
+        let mut running: HashMap<Digraph, IntervalSums> = HashMap::new();
+        for [ev1, ev2] in self.windows(0) {
+            running.entry((ev1.key, ev2.key)).or_default().add(ev2.timestamp_us.saturating_sub(ev1.timestamp_us));
+        }
+        self.running = Some(running);
     }
 
-    pub fn get_key_events(&self) -> &VecDeque<KeyEvent> {
-        &self.events
+    // the digraph right after a correction is timed atypically while the user re-orients;
+    // skipping it removes that noise from every digraph-based statistic of buffered events
+    pub fn set_skip_post_correction(&mut self, skip: bool) {
+        self.skip_post_correction = skip;
+        if self.running.is_some() {
+            self.rebuild_running();
+        }
+    }
+
+    // sub-millisecond dwells suggest synthetic input, multi-second ones a held key
+    pub fn set_dwell_band(&mut self, min_dwell_ms: f64, max_dwell_ms: f64) {
+        self.dwell_band = Some((min_dwell_ms, max_dwell_ms));
+    }
+
+    // bounds memory to the number of distinct digraphs for long enrollments; the events
+    // buffered so far are folded in, and from then on get_key_events holds only the last two
+    // (the second to last is needed to tell a digraph right after a correction)
+    pub fn set_summary_only(&mut self) {
+        let mut summary: HashMap<Digraph, RunningStats> = HashMap::new();
+        for ([c1, c2], interval) in self.ngraph_intervals(0) {
+            summary.entry((c1, c2)).or_default().push(interval);
+        }
+        self.summary = Some(summary);
+        self.running = None;
+        while self.events.len() > 2 {
+            self.events.pop_front();
+        }
+    }
+
+    // shift pairs of the US QWERTY layout, shifted character -> base key
+    pub fn us_shift_normalizer() -> HashMap<char, char> {
+        let shifted = "~!@#$%^&*()_+{}|:\"<>?";
+        let base = "`1234567890-=[]\\;',./";
+
+        let mut normalizer: HashMap<char, char> = shifted.chars().zip(base.chars()).collect();
+        for c in 'A'..='Z' {
+            normalizer.insert(c, c.to_ascii_lowercase());
+        }
+        normalizer
+    }
+
+    pub fn set_key_normalizer(&mut self, key_normalizer: HashMap<char, char>) {
+        self.key_normalizer = Some(key_normalizer);
+    }
+
+    // marks a boundary (e.g. an ignored key) so that the previous and the next
+    // event don't form a digraph; without it ignored keys are simply skipped
+    pub fn add_digraph_break(&mut self) {
+        self.pending_break = true;
+    }
+
+    // index of the last event of every `len` (at least 2) consecutive events from `idx_start`
+    // on whose neighbors all form digraphs, see is_pair_counted
+    fn window_ends(&self, idx_start: usize, len: usize) -> impl Iterator<Item = usize> + '_ {
+        ((idx_start + len - 1)..self.events.len())
+            .filter(move |i| ((i + 2 - len)..=*i).all(|j| self.is_pair_counted(j)))
     }
 
-    pub fn clear_key_events(&mut self) {
-        self.events.clear();
+    // the events of every such window of N, N = 2 giving the digraphs and N = 3 the trigraphs
+    fn windows<const N: usize>(&self, idx_start: usize) -> impl Iterator<Item = [&KeyEvent; N]> {
+        self.window_ends(idx_start, N)
+            .map(move |i| core::array::from_fn(|j| &self.events[i + 1 + j - N]))
+    }
+
+    // the keys of every such window of N, with the time from its first to its last key
+    fn ngraph_intervals<const N: usize>(&self, idx_start: usize) -> impl Iterator<Item = ([char; N], f64)> + '_ {
+        self.windows::<N>(idx_start)
+            .map(|window| (window.map(|ev| ev.key), span_ms(window[0].timestamp_us, window[N-1].timestamp_us)))
+    }
+
+    // whether the events at `i-1` and `i` form a digraph
+    fn is_pair_counted(&self, i: usize) -> bool {
+        i >= 1 && i < self.events.len() && !self.events[i].follows_break && !self.is_post_correction(i)
+    }
+
+    // adds (or with `remove`, takes back) the pair ending at `i` to the running sums
+    fn update_running(&mut self, i: usize, remove: bool) {
+        let (ev1, ev2) = (&self.events[i-1], &self.events[i]);
+        let (k, interval_us) = ((ev1.key, ev2.key), ev2.timestamp_us.saturating_sub(ev1.timestamp_us));
+        if let Some(running) = &mut self.running {
+            if remove {
+                // every counted pair was added when it came in, so a miss means the sums
+                // went out of step with the buffer
+                debug_assert!(running.contains_key(&k), "running sums miss {:?}", k);
+                if let Some(sums) = running.get_mut(&k) {
+                    sums.remove(interval_us);
+                    if sums.n == 0 {
+                        running.remove(&k);
+                    }
+                }
+            } else {
+                running.entry(k).or_default().add(interval_us);
+            }
+        }
+    }
+
+    // whether the pair ending at `i` is the first one typed after a backspace (run)
+    fn is_post_correction(&self, i: usize) -> bool {
+        self.skip_post_correction && i >= 2
+            && self.events[i-2].key == BACKSPACE && self.events[i-1].key != BACKSPACE
+    }
+
+    pub fn add_key_event(&mut self, mut ev: KeyEvent) {
+        if self.pending_break {
+            ev.follows_break = true;
+            self.pending_break = false;
+        }
+
+        if let Some(normalizer) = &self.key_normalizer {
+            if let Some(key) = normalizer.get(&ev.key) {
+                ev.key = *key;
+            }
+        }
+
+        self.events.push_back(ev);
+        let n = self.events.len();
+        let counted = self.is_pair_counted(n - 1);
+        if let Some(summary) = &mut self.summary {
+            if counted {
+                let (ev1, ev2) = (&self.events[n-2], &self.events[n-1]);
+                summary.entry((ev1.key, ev2.key)).or_default()
+                    .push(span_ms(ev1.timestamp_us, ev2.timestamp_us));
+            }
+            while self.events.len() > 2 {
+                self.events.pop_front();
+            }
+            return;
+        }
+        if self.running.is_some() && counted {
+            self.update_running(n - 1, false);
+        }
+        // set_events_limit already trimmed the buffer, so one event over is the most it can be
+        if self.events_limit.is_some_and(|limit| self.events.len() > limit) {
+            self.evict_front();
+        }
+        self.evict_expired();
+    }
+
+    // drops the events older than time_window_ms before the latest one
+    fn evict_expired(&mut self) {
+        // with an events limit of 0 the buffer can already be empty
+        if let (Some(window_ms), Some(latest)) = (self.time_window_ms, self.events.back()) {
+            let cutoff = latest.timestamp_us.saturating_sub(window_ms * 1000);
+            while self.events.front().is_some_and(|ev| ev.timestamp_us < cutoff) {
+                self.evict_front();
+            }
+        }
+    }
+
+    fn evict_front(&mut self) {
+        if self.running.is_some() {
+            // the front pair goes away, and the next one may stop following a correction
+            if self.is_pair_counted(1) {
+                self.update_running(1, true);
+            }
+            if self.is_pair_counted(2) {
+                self.update_running(2, true);
+            }
+        }
+        let ev1 = match self.events.pop_front() {
+            Some(ev) => ev,
+            None => return,
+        };
+        if let (Some(evicted), Some(ev2)) = (&mut self.evicted, self.events.front()) {
+            // the event before ev1 is gone from the buffer, is_post_correction can't see it
+            let post_correction = self.skip_post_correction
+                && self.evicted_key == Some(BACKSPACE) && ev1.key != BACKSPACE;
+            if !ev2.follows_break && !post_correction {
+                evicted.entry((ev1.key, ev2.key)).or_default().push(span_ms(ev1.timestamp_us, ev2.timestamp_us));
+            }
+        }
+        self.evicted_key = Some(ev1.key);
+        if self.running.is_some() && self.is_pair_counted(1) {
+            self.update_running(1, false);
+        }
+    }
+
+    // the release time stays unknown until add_key_release, rather than defaulting to the
+    // press: a zero dwell would count as a real hold in the dwell and up-* statistics
+    pub fn add_keystroke(&mut self, key: char) {
+        let ts = self.clock.now_us();
+        self.add_key_event(KeyEvent::from_micros(ts, key));
+    }
+
+    // stamps the release of the latest still-held press of `key`, for sources that report
+    // key-up separately; false when no such press is buffered
+    pub fn add_key_release(&mut self, key: char) -> bool {
+        let key = match &self.key_normalizer {
+            Some(normalizer) => *normalizer.get(&key).unwrap_or(&key),
+            None => key,
+        };
+        let ts = self.clock.now_us();
+        match self.events.iter_mut().rev().find(|ev| ev.key == key && ev.release_us.is_none()) {
+            Some(ev) => {
+                ev.release_us = Some(ts.max(ev.timestamp_us));
+                true
+            },
+            None => false,
+        }
+    }
+
+    // by default the buffer is a sliding window: once the front event is dropped, the digraph
+    // it formed with its successor no longer counts towards the statistics
+    pub fn set_events_limit(&mut self, limit: usize) {
+        self.events_limit = Some(limit);
+        while self.events.len() > limit {
+            self.evict_front();
+        }
+    }
+
+    // a sliding window over time instead of (or on top of) events_limit, whichever is
+    // tighter wins; evicted events are handled as with events_limit
+    pub fn set_time_window(&mut self, window_ms: u128) {
+        self.time_window_ms = Some(window_ms);
+        self.evict_expired();
+    }
+
+    // keeps the digraphs of events evicted by events_limit in a running accumulator, so
+    // compute_digraph_statistics covers everything logged instead of only the buffered window.
+    // Time-bounded statistics (compute_digraph_statistics_since) still see only the buffer.
+    pub fn retain_evicted_digraphs(&mut self, retain: bool) {
+        self.evicted = if retain { Some(HashMap::new()) } else { None };
+        self.evicted_key = None;
+    }
+
+    pub fn get_key_events(&self) -> &VecDeque<KeyEvent> {
+        &self.events
+    }
+
+    // stretches every interval by `factor`, keeping the earliest timestamp fixed. Used to map
+    // events from a device with another time base onto the profile's device. Means and stds
+    // scale alike, so dispersion-normalized diffs only change through the mean differences.
+    // The buffer need not be in time order (e.g. after jitter was added), hence the earliest
+    // rather than the front event as origin.
+    pub fn rescale_timestamps(&mut self, factor: f64) {
+        let origin = match self.events.iter().map(|ev| ev.timestamp_us).min() {
+            Some(origin) => origin,
+            None => return,
+        };
+        for ev in self.events.iter_mut() {
+            ev.timestamp_us = origin + ((ev.timestamp_us - origin) as f64 * factor).round() as u128;
+        }
+        if self.running.is_some() {
+            self.rebuild_running();
+        }
+    }
+
+    pub fn clear_key_events(&mut self) {
+        self.events.clear();
+        if let Some(summary) = &mut self.summary {
+            summary.clear();
+        }
+        if let Some(evicted) = &mut self.evicted {
+            evicted.clear();
+        }
+        self.evicted_key = None;
+        if let Some(running) = &mut self.running {
+            running.clear();
+        }
+    }
+
+    // raw intervals per digraph of the buffered events, what compute_digraph_statistics
+    // summarizes (apart from digraphs kept by summary-only mode or retained evictions)
+    pub fn compute_digraph_samples(&self) -> HashMap<Digraph, Vec<f64>> {
+        let mut samples: HashMap<Digraph, Vec<f64>> = HashMap::new();
+        for ([c1, c2], interval) in self.ngraph_intervals(0) {
+            samples.entry((c1, c2)).or_default().push(interval);
+        }
+        samples
+    }
+
+    pub fn compute_digraph_statistics(&self) -> HashMap<Digraph, DigraphStats> {
+        if let Some(summary) = &self.summary {
+            return summary.iter().filter_map(|(k, v)| v.to_stats().map(|stats| (*k, stats))).collect();
+        }
+        if let Some(evicted) = &self.evicted {
+            if !evicted.is_empty() {
+                let mut all = evicted.clone();
+                for ([c1, c2], interval) in self.ngraph_intervals(0) {
+                    all.entry((c1, c2)).or_default().push(interval);
+                }
+                return all.iter().filter_map(|(k, v)| v.to_stats().map(|stats| (*k, stats))).collect();
+            }
+        }
+        if let Some(running) = &self.running {
+            return running.iter().filter_map(|(k, v)| v.to_stats().map(|stats| (*k, stats))).collect();
+        }
+        self.compute_digraph_statistics_from(0)
+    }
+
+    // like compute_digraph_statistics after trimming outliers, over the buffered events only
+    pub fn compute_digraph_statistics_with(&self, options: &StatisticsOptions) -> HashMap<Digraph, DigraphStats> {
+        self.compute_digraph_samples().iter()
+            .filter_map(|(k, v)| summarize(&options.trim(v)).map(|stats| (*k, stats)))
+            .collect()
+    }
+
+    // statistics over the events within `ms_ago` milliseconds of the latest event
+    pub fn compute_digraph_statistics_since(&self, ms_ago: u128) -> HashMap<Digraph, DigraphStats> {
+        let latest = match self.events.back() {
+            Some(ev) => ev.timestamp_us,
+            None => return HashMap::new(),
+        };
+        let cutoff = latest.saturating_sub(ms_ago * 1000);
+        self.compute_digraph_statistics_from(self.events.partition_point(|ev| ev.timestamp_us < cutoff))
+    }
+
+    fn compute_digraph_statistics_from(&self, idx_start: usize) -> HashMap<Digraph, DigraphStats> {
+        if self.events.len() - idx_start <= SMALL_SAMPLE_LIMIT
+            && self.events.range(idx_start..).all(|ev| ev.key.is_ascii_lowercase()) {
+            return self.compute_letter_digraph_statistics_from(idx_start);
+        }
+        self.compute_hashed_digraph_statistics_from(idx_start)
+    }
+
+    // fast path for small windows, samples are bucketed in a dense 26x26 table
+    fn compute_letter_digraph_statistics_from(&self, idx_start: usize) -> HashMap<Digraph, DigraphStats> {
+        let index = |c: char| (c as u8 - b'a') as usize;
+        let mut samples: Vec<Vec<f64>> = vec![Vec::new(); 26 * 26];
+        for ([c1, c2], interval) in self.ngraph_intervals(idx_start) {
+            samples[index(c1) * 26 + index(c2)].push(interval);
+        }
+
+        let mut stats: HashMap<Digraph, DigraphStats> = HashMap::new();
+        for (i, v) in samples.iter().enumerate() {
+            if let Some(digraph_stats) = summarize(v) {
+                let k = ((b'a' + (i / 26) as u8) as char, (b'a' + (i % 26) as u8) as char);
+                stats.insert(k, digraph_stats);
+            }
+        }
+        stats
+    }
+
+    fn compute_hashed_digraph_statistics_from(&self, idx_start: usize) -> HashMap<Digraph, DigraphStats> {
+        let mut samples: HashMap<Digraph, Vec<f64>> = HashMap::new();
+        for ([c1, c2], interval) in self.ngraph_intervals(idx_start) {
+            samples.entry((c1, c2)).or_default().push(interval);
+        }
+
+        summarize_samples(&samples)
+    }
+
+    // time from the first to the last key of every `n` consecutive keys, n=2 being the
+    // digraphs. Windows containing a digraph break are left out.
+    pub fn compute_ngraph_statistics(&self, n: usize) -> HashMap<Vec<char>, DigraphStats> {
+        if n < 2 {
+            return HashMap::new();
+        }
+        let mut samples: HashMap<Vec<char>, Vec<f64>> = HashMap::new();
+        for i in self.window_ends(0, n) {
+            let first = i + 1 - n;
+            let k: Vec<char> = self.events.range(first..=i).map(|ev| ev.key).collect();
+            samples.entry(k).or_default().push(span_ms(self.events[first].timestamp_us, self.events[i].timestamp_us));
+        }
+
+        summarize_samples(&samples)
+    }
+
+    pub fn compute_timing_statistics(&self, feature: TimingFeature) -> HashMap<Digraph, DigraphStats> {
+        if feature == TimingFeature::DownDown {
+            return self.compute_digraph_statistics();
+        }
+
+        let mut samples: HashMap<Digraph, Vec<f64>> = HashMap::new();
+        for [ev1, ev2] in self.windows(0) {
+            if let Some(v) = feature.interval(ev1, ev2) {
+                samples.entry((ev1.key, ev2.key)).or_default().push(v);
+            }
+        }
+
+        summarize_samples(&samples)
+    }
+
+    // fraction of consecutive keys pressed before the previous one was released,
+    // over the pairs whose release time is known
+    pub fn compute_rollover_rate(&self) -> Option<f64> {
+        let flags: Vec<bool> = self.windows(0)
+            .filter_map(|[ev1, ev2]| ev1.is_rolled_over_by(ev2))
+            .collect();
+        if flags.is_empty() {
+            return None;
+        }
+        Some(flags.iter().filter(|f| **f).count() as f64 / flags.len() as f64)
+    }
+
+    // time from the first to the third key over every consecutive triple
+    pub fn compute_trigraph_statistics(&self) -> HashMap<Trigraph, DigraphStats> {
+        self.compute_trigraph_statistics_where(|_| true)
+    }
+
+    // time from the first to the third key, only for the trigraphs in `allowlist`; the full
+    // trigraph space is mostly empty, so restricting it keeps profiles small and entries reliable
+    pub fn compute_trigraph_statistics_in(&self, allowlist: &HashSet<Trigraph>) -> HashMap<Trigraph, DigraphStats> {
+        self.compute_trigraph_statistics_where(|k| allowlist.contains(k))
+    }
+
+    fn compute_trigraph_statistics_where(&self, keep: impl Fn(&Trigraph) -> bool) -> HashMap<Trigraph, DigraphStats> {
+        let mut samples: HashMap<Trigraph, Vec<f64>> = HashMap::new();
+        for ([c1, c2, c3], interval) in self.ngraph_intervals(0) {
+            if keep(&(c1, c2, c3)) {
+                samples.entry((c1, c2, c3)).or_default().push(interval);
+            }
+        }
+
+        summarize_samples(&samples)
+    }
+
+    // pairs of keys `skip` positions apart (skip=1 gives the usual digraphs), timed from the
+    // first to the second. Pairs spanning a digraph break are left out.
+    pub fn compute_skipgram_statistics(&self, skip: usize) -> HashMap<Digraph, DigraphStats> {
+        if skip == 0 {
+            return HashMap::new();
+        }
+        let mut samples: HashMap<Digraph, Vec<f64>> = HashMap::new();
+        for i in self.window_ends(0, skip + 1) {
+            let (ev1, ev2) = (&self.events[i-skip], &self.events[i]);
+            samples.entry((ev1.key, ev2.key)).or_default().push(span_ms(ev1.timestamp_us, ev2.timestamp_us));
+        }
+
+        summarize_samples(&samples)
+    }
+
+    // one statistics map per timing feature
+    pub fn compute_all_timing_statistics(&self) -> HashMap<TimingFeature, HashMap<Digraph, DigraphStats>> {
+        TimingFeature::ALL.iter().map(|f| (*f, self.compute_timing_statistics(*f))).collect()
+    }
+
+    // interval preceding each key, pooled over every predecessor
+    pub fn compute_key_arrival_statistics(&self) -> HashMap<char, DigraphStats> {
+        let mut samples: HashMap<char, Vec<f64>> = HashMap::new();
+        for ([_, c2], interval) in self.ngraph_intervals(0) {
+            samples.entry(c2).or_default().push(interval);
+        }
+
+        summarize_samples(&samples)
+    }
+
+    // per-key hold duration, events without a release time are ignored
+    pub fn compute_dwell_statistics(&self) -> HashMap<char, DigraphStats> {
+        let mut samples: HashMap<char, Vec<f64>> = HashMap::new();
+        for ev in self.events.iter() {
+            if let Some(release_us) = ev.release_us {
+                let dwell = span_ms(ev.timestamp_us, release_us);
+                if let Some((min_dwell_ms, max_dwell_ms)) = self.dwell_band {
+                    if dwell < min_dwell_ms || dwell > max_dwell_ms {
+                        continue;
+                    }
+                }
+                samples.entry(ev.key).or_default().push(dwell);
+            }
+        }
+
+        summarize_samples(&samples)
+    }
+
+    // fraction of events which are backspaces
+    pub fn compute_correction_rate(&self) -> Option<f64> {
+        if self.events.is_empty() {
+            return None;
+        }
+        Some(self.events.iter().filter(|ev| ev.key == BACKSPACE).count() as f64 / self.events.len() as f64)
+    }
+
+    // per-key statistics of analog pressure, events without pressure are ignored
+    pub fn compute_pressure_statistics(&self) -> HashMap<char, DigraphStats> {
+        let mut samples: HashMap<char, Vec<f64>> = HashMap::new();
+        for ev in self.events.iter() {
+            if let Some(pressure) = ev.pressure {
+                samples.entry(ev.key).or_default().push(pressure as f64);
+            }
+        }
+
+        summarize_samples(&samples)
+    }
+
+    // CSV layout shared with public keystroke datasets: key,press_time,release_time (ms),
+    // the release time is left empty when unknown. Sub-millisecond times get decimals.
+    pub fn serialize_events_csv(events: &VecDeque<KeyEvent>) -> String {
+        let format_us = |us: u128| match us % 1000 {
+            0 => (us / 1000).to_string(),
+            frac => format!("{}.{:03}", us / 1000, frac),
+        };
+        let mut csv = String::from("key,press_time,release_time\n");
+        for ev in events.iter() {
+            let release = ev.release_us.map(format_us).unwrap_or_default();
+            csv.push_str(&format!("{},{},{}\n", escape_csv_field(&ev.key.to_string()), format_us(ev.timestamp_us), release));
+        }
+        csv
+    }
+
+    pub fn deserialize_events_csv(serialized: &str) -> Result<VecDeque<KeyEvent>, KeynomeError> {
+        let mut events = VecDeque::new();
+        for (i, fields) in parse_csv_records(serialized).iter().enumerate() {
+            if (fields.len() == 1 && fields[0].is_empty()) || (i == 0 && fields[0] == "key") {
+                continue;
+            }
+
+            let err = |msg: &str| KeynomeError::Parse(format!("line {}: {}", i + 1, msg));
+            if fields.len() < 2 || fields.len() > 3 {
+                return Err(err("expected key,press_time,release_time"));
+            }
+
+            let mut chars = fields[0].chars();
+            let key = match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(err("key has to be a single character")),
+            };
+            let press = parse_ms_as_us(fields[1].trim()).ok_or_else(|| err("invalid press_time"))?;
+
+            let mut ev = KeyEvent::from_micros(press, key);
+            if let Some(release) = fields.get(2).map(|f| f.trim()).filter(|f| !f.is_empty()) {
+                ev = ev.with_release_us(parse_ms_as_us(release).ok_or_else(|| err("invalid release_time"))?);
+            }
+            events.push_back(ev);
+        }
+        Ok(events)
+    }
+
+    // {"a-b": {"size_samples": .., "mean": .., "std": ..}, ...}
+    pub fn serialize_digraph_statistics(stats: &HashMap<Digraph, DigraphStats>) -> String {
+        let mut str_keyed_map: HashMap<String, &DigraphStats> = HashMap::new();
+        for (k, v) in stats.iter() {
+            str_keyed_map.insert(format_digraph_key(k), v);
+        }
+        serde_json::to_string(&str_keyed_map).unwrap()
+    }
+
+    // panics on malformed input, see try_deserialize_digraph_statistics
+    pub fn deserialize_digraph_statistics(serialized: &str) -> HashMap<Digraph, DigraphStats> {
+        KeystrokeLogger::try_deserialize_digraph_statistics(serialized).unwrap()
+    }
+
+    // also accepts the legacy layout where every entry is a stringified JSON object
+    pub fn try_deserialize_digraph_statistics(serialized: &str) -> Result<HashMap<Digraph, DigraphStats>, ProfileError> {
+        let mut stats: HashMap<Digraph, DigraphStats> = HashMap::new();
+        let str_keyed_map: HashMap<String, serde_json::Value> = serde_json::from_str(serialized)?;
+
+        for (k, v) in str_keyed_map {
+            // keys are split by position, so '-' itself is a valid key on either side
+            let digraph = parse_digraph_key(&k).ok_or(ProfileError::InvalidDigraphKey(k.clone()))?;
+            let digraph_stats: DigraphStats = match v {
+                serde_json::Value::String(legacy) => serde_json::from_str(&legacy),
+                v => serde_json::from_value(v),
+            }?;
+
+            stats.insert(digraph, digraph_stats);
+        }
+        Ok(stats)
+    }
+}
+
+// marks every digraph as observed at `timestamp_ms`
+pub fn stamp_last_updated(stats: &mut HashMap<Digraph, DigraphStats>, timestamp_ms: u64) {
+    for v in stats.values_mut() {
+        v.last_updated_ms = Some(timestamp_ms);
+    }
+}
+
+// std floor for z-scores, so that a near-constant profile digraph doesn't blow up the diff
+const MIN_STD_MS: f64 = 1.0;
+
+// per-digraph distance between the profile and the sample
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum DistanceMetric {
+    // |Δmean|, compute_diff treats it as Scaled when `dispersion` is set
+    #[default]
+    Absolute,
+    // |Δmean| / (0.001 + profile std)
+    Scaled,
+    // |Δmean| in profile standard deviations, with the std floored at MIN_STD_MS
+    ZScore,
+    // Δmean², penalizing a few large deviations over many small ones
+    Squared,
+    // |Δmean| in standard deviations pooled from both sides, floored at MIN_STD_MS
+    PooledZScore,
+    // euclidean distance of (mean, std) from the profile's, in profile standard deviations;
+    // a diagonal Mahalanobis distance that also catches a change in consistency
+    Mahalanobis,
+}
+
+// extra per-digraph weight from how common the digraph is, IDF style: ln(1 + 1/frequency),
+// so that rare digraphs count more than ones that are frequent merely by language
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum FrequencyWeighting {
+    #[default]
+    Uniform,
+    // frequencies from the profile's own sample counts
+    ProfileCounts,
+    // frequencies from diff_params.frequency_table, unlisted digraphs count as the rarest listed
+    Table,
+}
+
+// alternative matching mode: each profile digraph accepts sample means within
+// mean ± width·std, and a window is accepted when at least min_fraction of the compared
+// digraphs fall inside their band
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AcceptanceBands {
+    pub width: f64,
+    pub min_fraction: f64,
+}
+
+// fields missing from profiles of other tool versions take their Default value
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct KeynomeAuthenticatorDiffParams {
+    pub dispersion: bool,
+    pub min_instances: u32,
+    pub max_comparisons: u32,
+    // shifted characters were pooled with their base key (us_shift_normalizer) when enrolling
+    #[serde(default)]
+    pub normalize_shift: bool,
+    // ignored keys broke digraphs (add_digraph_break) instead of joining their neighbors when enrolling
+    #[serde(default)]
+    pub break_on_ignored: bool,
+    // explicit per-digraph weights, digraphs not listed weigh 1.0
+    #[serde(default, with = "serde_digraph_weights")]
+    pub weights: Option<HashMap<Digraph, f64>>,
+    // minimum instances on the sample side, falls back to min_instances
+    #[serde(default)]
+    pub sample_min_instances: Option<u32>,
+    // minimum fraction of eligible profile digraphs the sample has to cover
+    #[serde(default)]
+    pub min_overlap_ratio: Option<f64>,
+    // compare only the N profile digraphs with the most instances
+    #[serde(default)]
+    pub top_n: Option<usize>,
+    // rescale the sample to the profile's overall typing speed, comparing relative rhythm only
+    #[serde(default)]
+    pub normalize_speed: bool,
+    // mean differences below this many ms contribute nothing, even with dispersion
+    #[serde(default)]
+    pub tolerance_ms: f64,
+    #[serde(default)]
+    pub metric: DistanceMetric,
+    // digraphs not updated for this long before the profile's most recent update
+    // are multiplied by stale_weight
+    #[serde(default)]
+    pub stale_after_ms: Option<u64>,
+    pub stale_weight: f64,
+    // enrollment windows left out of diff_base, the first ones reflect unsettled typing
+    pub skip_windows: usize,
+    // decide by per-digraph bands instead of thresholding the summed diff
+    pub acceptance_bands: Option<AcceptanceBands>,
+    pub frequency_weighting: FrequencyWeighting,
+    // relative digraph frequencies, e.g. of the typed language, as {"a-b": 0.012, ...}
+    #[serde(default, with = "serde_digraph_weights")]
+    pub frequency_table: Option<HashMap<Digraph, f64>>,
+    // compare medians and iqrs instead of means and stds where both sides have them,
+    // which bursty pauses barely move
+    pub use_median: bool,
+    // weigh each digraph by min(profile count, sample count) and divide the diff by the
+    // total weight, so well-sampled digraphs dominate and the scale no longer grows with overlap
+    pub count_weighting: bool,
+}
+
+impl Default for KeynomeAuthenticatorDiffParams {
+    fn default() -> Self {
+        KeynomeAuthenticatorDiffParams {
+            dispersion: false,
+            min_instances: 2,
+            max_comparisons: 100,
+            normalize_shift: false,
+            break_on_ignored: false,
+            weights: None,
+            sample_min_instances: None,
+            min_overlap_ratio: None,
+            top_n: None,
+            normalize_speed: false,
+            tolerance_ms: 0.0,
+            metric: DistanceMetric::Absolute,
+            stale_after_ms: None,
+            stale_weight: 0.5,
+            skip_windows: 0,
+            acceptance_bands: None,
+            frequency_weighting: FrequencyWeighting::Uniform,
+            frequency_table: None,
+            use_median: false,
+            count_weighting: false,
+        }
+    }
+}
+
+impl KeynomeAuthenticatorDiffParams {
+    // parses weights given as {"a-b": 1.5, ...}; panics on malformed input, see try_parse_digraph_weights
+    pub fn parse_digraph_weights(serialized: &str) -> HashMap<Digraph, f64> {
+        KeynomeAuthenticatorDiffParams::try_parse_digraph_weights(serialized).unwrap()
+    }
+
+    pub fn try_parse_digraph_weights(serialized: &str) -> Result<HashMap<Digraph, f64>, ProfileError> {
+        let str_keyed_map: HashMap<String, f64> = serde_json::from_str(serialized)?;
+
+        let mut weights: HashMap<Digraph, f64> = HashMap::new();
+        for (k, v) in str_keyed_map {
+            let digraph = parse_digraph_key(&k).ok_or(ProfileError::InvalidDigraphKey(k.clone()))?;
+            weights.insert(digraph, v);
+        }
+        Ok(weights)
+    }
+
+    // whether every per-digraph contribution is non-negative
+    pub fn is_monotone(&self) -> bool {
+        if self.count_weighting {
+            return false;
+        }
+        match &self.weights {
+            Some(weights) => weights.values().all(|w| *w >= 0.0),
+            None => true,
+        }
+    }
+
+    fn weight(&self, k: &Digraph) -> f64 {
+        match &self.weights {
+            Some(weights) => *weights.get(k).unwrap_or(&1.0),
+            None => 1.0,
+        }
+    }
+
+    // IDF-style weight of every profile digraph, None with uniform weighting. A table without
+    // any positive frequency also yields None: every weight would be 0, and so every diff.
+    fn frequency_weights(&self, stats_profile: &HashMap<Digraph, DigraphStats>) -> Option<HashMap<Digraph, f64>> {
+        let idf = |frequency: f64| (1.0 + 1.0 / frequency).ln();
+        match (self.frequency_weighting, &self.frequency_table) {
+            (FrequencyWeighting::ProfileCounts, _) => {
+                let total: usize = stats_profile.values().map(|v| v.size_samples).sum();
+                Some(stats_profile.iter()
+                    .filter(|(_, v)| v.size_samples > 0)
+                    .map(|(k, v)| (*k, idf(v.size_samples as f64 / total as f64)))
+                    .collect())
+            },
+            (FrequencyWeighting::Table, Some(table)) => {
+                let rarest = table.values().cloned().filter(|f| *f > 0.0).fold(f64::INFINITY, f64::min);
+                if rarest.is_infinite() {
+                    return None;
+                }
+                Some(stats_profile.keys()
+                    .map(|k| (*k, idf(table.get(k).cloned().filter(|f| *f > 0.0).unwrap_or(rarest))))
+                    .collect())
+            },
+            _ => None,
+        }
+    }
+}
+
+// overall mean interval, i.e. the digraph means weighted by their instances
+pub fn mean_interval(stats: &HashMap<Digraph, DigraphStats>) -> Option<f64> {
+    let n: usize = stats.values().map(|v| v.size_samples).sum();
+    if n == 0 {
+        return None;
+    }
+    Some(stats.values().map(|v| v.mean * v.size_samples as f64).sum::<f64>() / n as f64)
+}
+
+// effective timestamp resolution in ms: the gcd of all nonzero intervals between
+// consecutive presses. 1ms clocks give at most 1.0; values around 10-16ms mean many
+// intervals collapse onto the same few values. A tick that isn't a whole number of ms
+// (e.g. 15.6ms) has no common divisor and reads as the plain ms resolution instead.
+pub fn estimate_clock_granularity_ms(events: &VecDeque<KeyEvent>) -> Option<f64> {
+    fn gcd(a: u128, b: u128) -> u128 {
+        if b == 0 { a } else { gcd(b, a % b) }
+    }
+
+    let granularity_us = events.iter().zip(events.iter().skip(1))
+        .map(|(ev1, ev2)| ev2.timestamp_us.saturating_sub(ev1.timestamp_us))
+        .filter(|us| *us > 0)
+        .fold(0, gcd);
+    if granularity_us == 0 {
+        return None;
+    }
+    Some(granularity_us as f64 / 1000.0)
+}
+
+// granularity above which captured timings are too coarse for reliable features
+pub const COARSE_CLOCK_MS: f64 = 5.0;
+
+// complete windows of `n_sample` events starting every `stride` events within the last
+// `n_total` events, oldest first; the same windowing compute_diff_base uses (stride = n_sample)
+pub fn extract_windows(events: &VecDeque<KeyEvent>, n_total: usize, n_sample: usize, stride: usize)
+    -> Vec<VecDeque<KeyEvent>> {
+    if n_sample == 0 || stride == 0 || n_total > events.len() || n_sample > n_total {
+        return Vec::new();
+    }
+
+    let offset = events.len() - n_total;
+    (0..=(n_total - n_sample)).step_by(stride)
+        .map(|i| events.range((offset + i)..(offset + i + n_sample)).cloned().collect())
+        .collect()
+}
+
+fn truncate_digraph_statistics(stats: &mut HashMap<Digraph, DigraphStats>, max_digraphs: usize) {
+    if stats.len() <= max_digraphs {
+        return;
+    }
+    let mut ranked: Vec<(Digraph, usize)> = stats.iter().map(|(k, v)| (*k, v.size_samples)).collect();
+    ranked.sort_by(|(ka, na), (kb, nb)| nb.cmp(na).then(ka.cmp(kb)));
+    for (k, _) in &ranked[max_digraphs..] {
+        stats.remove(k);
+    }
+}
+
+fn digraph_statistics_of<'a>(events: impl IntoIterator<Item = &'a KeyEvent>) -> HashMap<Digraph, DigraphStats> {
+    let mut kstr = KeystrokeLogger::detached();
+    for ev in events {
+        kstr.add_key_event(ev.clone());
+    }
+    kstr.compute_digraph_statistics()
+}
+
+// stand-in for a digraph missing from the profile: among the stored digraphs sharing a key
+// with `target`, the one whose mean is closest to their average, along with that mean.
+// A stored target is returned as is.
+pub fn nearest_digraph(stats_profile: &HashMap<Digraph, DigraphStats>, target: Digraph) -> Option<(Digraph, f64)> {
+    if let Some(v) = stats_profile.get(&target) {
+        return Some((target, v.mean));
+    }
+
+    let mut neighbors: Vec<(&Digraph, &DigraphStats)> = stats_profile.iter()
+        .filter(|(k, _)| k.0 == target.0 || k.1 == target.1)
+        .collect();
+    if neighbors.is_empty() {
+        return None;
+    }
+    neighbors.sort_by_key(|(k, _)| **k);
+
+    let expected = neighbors.iter().map(|(_, v)| v.mean).sum::<f64>() / neighbors.len() as f64;
+    neighbors.iter()
+        .min_by(|(_, va), (_, vb)| (va.mean - expected).abs().partial_cmp(&(vb.mean - expected).abs()).unwrap())
+        .map(|(k, v)| (**k, v.mean))
+}
+
+// how the per-window diffs are weighted when averaged into diff_base
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowWeighting {
+    Uniform,
+    // the i-th window (oldest first) weighs i + 1
+    Linear,
+}
+
+impl WindowWeighting {
+    pub fn weighted_mean(&self, diffs: &[f64]) -> f64 {
+        let weights: Vec<f64> = match self {
+            WindowWeighting::Uniform => vec![1.0; diffs.len()],
+            WindowWeighting::Linear => (1..=diffs.len()).map(|i| i as f64).collect(),
+        };
+        let total: f64 = weights.iter().sum();
+        diffs.iter().zip(weights.iter()).map(|(d, w)| d * w).sum::<f64>() / total
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuthResult {
+    pub accepted: bool,
+    pub diff: f64,
+    pub threshold: f64,
+    pub n_comparisons: u32,
+    // 1 - min(diff / threshold, 1): 1.0 for a perfect match, 0.0 at or beyond the threshold.
+    // Always 0.0 when nothing could be decided: n_comparisons == 0 (diff is then a
+    // meaningless 0.0), too few events, or less than min_overlap_ratio covered.
+    // With acceptance_bands it is the fraction of compared digraphs within their band,
+    // and that fraction rather than the diff decides acceptance.
+    pub confidence: f64,
+}
+
+pub struct KeynomeAuthenticator<'a, 'b> {
+    pub diff_base: f64,
+    pub multiplier: f64,
+    // samples with fewer events are rejected outright, 0 (the default) accepts any length
+    pub n_sample: usize,
+    pub diff_params: &'a KeynomeAuthenticatorDiffParams,
+    pub stats: &'b HashMap<Digraph, DigraphStats>,
+}
+
+impl<'a, 'b> KeynomeAuthenticator<'a, 'b> {
+    pub fn new(diff_base: f64, multiplier: f64, diff_params: &'a KeynomeAuthenticatorDiffParams,
+               stats: &'b HashMap<Digraph, DigraphStats>) -> KeynomeAuthenticator<'a, 'b> {
+        KeynomeAuthenticator { diff_base, multiplier, n_sample: 0, diff_params, stats }
+    }
+
+    pub fn with_n_sample(mut self, n_sample: usize) -> KeynomeAuthenticator<'a, 'b> {
+        self.n_sample = n_sample;
+        self
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.diff_base * self.multiplier
+    }
+
+    // compute_diff against this authenticator's profile and params
+    pub fn diff(&self, stats_sample: &HashMap<Digraph, DigraphStats>) -> f64 {
+        KeynomeAuthenticator::compute_diff(self.stats, stats_sample, self.diff_params)
+    }
+
+    pub fn compute_diff(
+        stats_profile: &HashMap<Digraph, DigraphStats>, stats_sample: &HashMap<Digraph, DigraphStats>,
+        diff_params: &KeynomeAuthenticatorDiffParams) -> f64 {
+        KeynomeAuthenticator::compute_diff_with_limit(stats_profile, stats_sample, diff_params, f64::INFINITY)
+    }
+
+    // whole pipeline on raw events: `a` plays the profile, `b` the sample
+    pub fn diff_events(a: &VecDeque<KeyEvent>, b: &VecDeque<KeyEvent>,
+                       diff_params: &KeynomeAuthenticatorDiffParams) -> f64 {
+        KeynomeAuthenticator::compute_diff(&digraph_statistics_of(a), &digraph_statistics_of(b), diff_params)
+    }
+
+    // fraction of the profile digraphs with enough instances which the sample also covers
+    pub fn overlap_ratio(
+        stats_profile: &HashMap<Digraph, DigraphStats>, stats_sample: &HashMap<Digraph, DigraphStats>,
+        diff_params: &KeynomeAuthenticatorDiffParams) -> f64 {
+
+        let sample_min_instances = diff_params.sample_min_instances.unwrap_or(diff_params.min_instances);
+        let eligible: Vec<&Digraph> = stats_profile.iter()
+            .filter(|(_, v)| v.size_samples >= diff_params.min_instances as usize)
+            .map(|(k, _)| k)
+            .collect();
+        if eligible.is_empty() {
+            return 0.0;
+        }
+
+        let n_covered = eligible.iter()
+            .filter(|k| stats_sample.get(**k).is_some_and(|vs| vs.size_samples >= sample_min_instances as usize))
+            .count();
+        n_covered as f64 / eligible.len() as f64
+    }
+
+    pub fn has_sufficient_overlap(
+        stats_profile: &HashMap<Digraph, DigraphStats>, stats_sample: &HashMap<Digraph, DigraphStats>,
+        diff_params: &KeynomeAuthenticatorDiffParams) -> bool {
+        match diff_params.min_overlap_ratio {
+            Some(min_ratio) => KeynomeAuthenticator::overlap_ratio(stats_profile, stats_sample, diff_params) >= min_ratio,
+            None => true,
+        }
+    }
+
+    // None when the sample shares no comparable digraph with the profile (or covers less
+    // than min_overlap_ratio of it), since a diff of 0.0 would otherwise look like a perfect match
+    pub fn compute_diff_checked(
+        stats_profile: &HashMap<Digraph, DigraphStats>, stats_sample: &HashMap<Digraph, DigraphStats>,
+        diff_params: &KeynomeAuthenticatorDiffParams) -> Option<f64> {
+        if !KeynomeAuthenticator::has_sufficient_overlap(stats_profile, stats_sample, diff_params) {
+            return None;
+        }
+        match KeynomeAuthenticator::compute_diff_counted(stats_profile, stats_sample, diff_params, f64::INFINITY) {
+            (_, 0) => None,
+            (diff, _) => Some(diff),
+        }
+    }
+
+    // fraction of the compared digraphs whose sample mean lies within `width` profile stds
+    // of the profile mean, None when nothing is comparable
+    pub fn compute_band_fraction(
+        stats_profile: &HashMap<Digraph, DigraphStats>, stats_sample: &HashMap<Digraph, DigraphStats>,
+        diff_params: &KeynomeAuthenticatorDiffParams, width: f64) -> Option<f64> {
+        let sample_min_instances = diff_params.sample_min_instances.unwrap_or(diff_params.min_instances);
+        let mut n_within = 0;
+        let mut n_comparisons = 0;
+        for (k, v) in stats_profile.iter() {
+            if v.size_samples < diff_params.min_instances as usize {
+                continue;
+            }
+            if let Some(vs) = stats_sample.get(k) {
+                if vs.size_samples < sample_min_instances as usize {
+                    continue;
+                }
+                if (vs.mean - v.mean).abs() <= width * v.std.max(MIN_STD_MS) {
+                    n_within += 1;
+                }
+                n_comparisons += 1;
+            }
+            if n_comparisons >= diff_params.max_comparisons {
+                break;
+            }
+        }
+
+        if n_comparisons == 0 || !KeynomeAuthenticator::has_sufficient_overlap(stats_profile, stats_sample, diff_params) {
+            return None;
+        }
+        Some(n_within as f64 / n_comparisons as f64)
+    }
+
+    // stops comparing as soon as the diff exceeds `limit`; the returned partial diff is then
+    // already above the limit. Early exit only applies while the diff cannot decrease.
+    pub fn compute_diff_with_limit(
+        stats_profile: &HashMap<Digraph, DigraphStats>, stats_sample: &HashMap<Digraph, DigraphStats>,
+        diff_params: &KeynomeAuthenticatorDiffParams, limit: f64) -> f64 {
+        KeynomeAuthenticator::compute_diff_counted(stats_profile, stats_sample, diff_params, limit).0
+    }
+
+    // diff along with the number of digraphs actually compared
+    pub fn compute_diff_counted(
+        stats_profile: &HashMap<Digraph, DigraphStats>, stats_sample: &HashMap<Digraph, DigraphStats>,
+        diff_params: &KeynomeAuthenticatorDiffParams, limit: f64) -> (f64, u32) {
+        KeynomeAuthenticator::compute_diff_visiting(stats_profile, stats_sample, diff_params, limit, &mut |_| {})
+    }
+
+    // compute_diff_counted, calling `on_compared` with every digraph actually compared
+    pub fn compute_diff_visiting(
+        stats_profile: &HashMap<Digraph, DigraphStats>, stats_sample: &HashMap<Digraph, DigraphStats>,
+        diff_params: &KeynomeAuthenticatorDiffParams, limit: f64, on_compared: &mut dyn FnMut(&Digraph)) -> (f64, u32) {
+
+        let early_exit = diff_params.is_monotone();
+        let metric = match diff_params.metric {
+            DistanceMetric::Absolute if diff_params.dispersion => DistanceMetric::Scaled,
+            metric => metric,
+        };
+        let mut diff: f64 = 0.0;
+        let mut n_comparisons: u32 = 0;
+        let mut total_count_weight: f64 = 0.0;
+
+        // staleness is measured against the newest digraph, so no clock is needed
+        let stale_before = diff_params.stale_after_ms.and_then(|age| {
+            stats_profile.values().filter_map(|v| v.last_updated_ms).max().map(|newest| newest.saturating_sub(age))
+        });
+
+        let speed_factor = match (diff_params.normalize_speed, mean_interval(stats_profile), mean_interval(stats_sample)) {
+            (true, Some(mean_profile), Some(mean_sample)) if mean_sample > 0.0 => mean_profile / mean_sample,
+            _ => 1.0,
+        };
+
+        let frequency_weights = diff_params.frequency_weights(stats_profile);
+
+        let mut profile_digraphs: Vec<(&Digraph, &DigraphStats)> = stats_profile.iter().collect();
+        if let Some(top_n) = diff_params.top_n {
+            // most instances first, ties broken by digraph so the selection is deterministic
+            profile_digraphs.retain(|(_, v)| v.size_samples >= diff_params.min_instances as usize);
+            profile_digraphs.sort_by(|(ka, va), (kb, vb)| vb.size_samples.cmp(&va.size_samples).then(ka.cmp(kb)));
+            profile_digraphs.truncate(top_n);
+        }
+
+        for (k, v) in profile_digraphs {
+            if v.size_samples < diff_params.min_instances as usize {
+                continue;
+            }
+
+            if let Some(vs) = stats_sample.get(k) {
+                let sample_min_instances = diff_params.sample_min_instances.unwrap_or(diff_params.min_instances);
+                if vs.size_samples < sample_min_instances as usize {
+                    continue;
+                }
+
+                // borrowed unless the medians or the speed normalization call for a copy
+                let (v, vs) = if diff_params.use_median && v.has_quantiles() && vs.has_quantiles() {
+                    (Cow::Owned(v.robust()), Cow::Owned(vs.robust()))
+                } else {
+                    (Cow::Borrowed(v), Cow::Borrowed(vs))
+                };
+                let vs = if speed_factor == 1.0 {
+                    vs
+                } else {
+                    Cow::Owned(DigraphStats { mean: speed_factor * vs.mean, ..vs.into_owned() })
+                };
+                let count_weight = if diff_params.count_weighting {
+                    v.size_samples.min(vs.size_samples) as f64
+                } else {
+                    1.0
+                };
+                if (v.mean - vs.mean).abs() >= diff_params.tolerance_ms {
+                    let distance = v.distance(&vs, metric);
+                    let staleness = match (stale_before, v.last_updated_ms) {
+                        (Some(before), Some(updated)) if updated < before => diff_params.stale_weight,
+                        _ => 1.0,
+                    };
+                    let frequency_weight = frequency_weights.as_ref().and_then(|w| w.get(k).cloned()).unwrap_or(1.0);
+                    diff += diff_params.weight(k) * frequency_weight * staleness * count_weight * distance;
+                }
+                total_count_weight += count_weight;
+                n_comparisons += 1;
+                on_compared(k);
+
+                if early_exit && diff > limit {
+                    break;
+                }
+            }
+
+            if n_comparisons >= diff_params.max_comparisons {
+                break;
+            }
+        }
+        if diff_params.count_weighting && total_count_weight > 0.0 {
+            diff /= total_count_weight;
+        }
+        (diff, n_comparisons)
+    }
+
+    pub fn compute_diff_base(
+        events: &VecDeque<KeyEvent>, n_profile: usize, n_sample: usize,
+        diff_params: &KeynomeAuthenticatorDiffParams) -> Option<f64> {
+
+        KeynomeAuthenticator::compute_diff_base_weighted(events, n_profile, n_sample, diff_params, WindowWeighting::Uniform)
+    }
+
+    pub fn compute_diff_base_weighted(
+        events: &VecDeque<KeyEvent>, n_profile: usize, n_sample: usize,
+        diff_params: &KeynomeAuthenticatorDiffParams, weighting: WindowWeighting) -> Option<f64> {
+
+        let diffs = KeynomeAuthenticator::compute_diff_base_detailed(events, n_profile, n_sample, diff_params)?;
+        Some(weighting.weighted_mean(&diffs))
+    }
+
+    // genuine diffs of each n_sample window against the profile built from the last n_profile events
+    pub fn compute_diff_base_detailed(
+        events: &VecDeque<KeyEvent>, n_profile: usize, n_sample: usize,
+        diff_params: &KeynomeAuthenticatorDiffParams) -> Option<Vec<f64>> {
+
+        KeynomeAuthenticator::compute_window_diffs(events, n_profile, n_sample, diff_params, None)
+    }
+
+    // as compute_diff_base_detailed, with the profile cut down to max_digraphs first, so the
+    // base is calibrated on the same digraphs a truncated profile stores
+    pub fn compute_diff_base_detailed_truncated(
+        events: &VecDeque<KeyEvent>, n_profile: usize, n_sample: usize,
+        diff_params: &KeynomeAuthenticatorDiffParams, max_digraphs: usize) -> Option<Vec<f64>> {
+
+        KeynomeAuthenticator::compute_window_diffs(events, n_profile, n_sample, diff_params, Some(max_digraphs))
+    }
+
+    fn compute_window_diffs(
+        events: &VecDeque<KeyEvent>, n_profile: usize, n_sample: usize,
+        diff_params: &KeynomeAuthenticatorDiffParams, max_digraphs: Option<usize>) -> Option<Vec<f64>> {
+
+        if n_sample == 0 || n_profile > events.len() || !n_profile.is_multiple_of(n_sample) {
+            return None;
+        }
+
+        let mut stats = digraph_statistics_of(events.iter().skip(events.len() - n_profile));
+        if let Some(max_digraphs) = max_digraphs {
+            truncate_digraph_statistics(&mut stats, max_digraphs);
+        }
+        let diffs: Vec<f64> = extract_windows(events, n_profile, n_sample, n_sample).iter()
+            .skip(diff_params.skip_windows)
+            .map(|window| KeynomeAuthenticator::compute_diff(&stats, &digraph_statistics_of(window), diff_params))
+            .collect();
+
+        if diffs.is_empty() {
+            return None;
+        }
+        Some(diffs)
+    }
+
+    // accepts a sample whose diff is at most diff_base * multiplier. Samples shorter than
+    // n_sample, or sharing no comparable digraph with the profile, are rejected. With
+    // acceptance_bands the fraction of digraphs within their band decides instead.
+    pub fn authenticate(&self, events: &VecDeque<KeyEvent>) -> bool {
+        self.authenticate_with_score(events).accepted
+    }
+
+    pub fn authenticate_with_score(&self, events: &VecDeque<KeyEvent>) -> AuthResult {
+        let threshold = self.threshold();
+        if events.len() < self.n_sample {
+            return AuthResult { accepted: false, diff: 0.0, threshold, n_comparisons: 0, confidence: 0.0 };
+        }
+
+        let stats_sample = digraph_statistics_of(events);
+        let (diff, n_comparisons) = KeynomeAuthenticator::compute_diff_counted(
+            self.stats, &stats_sample, self.diff_params, f64::INFINITY);
+        let overlap = KeynomeAuthenticator::has_sufficient_overlap(self.stats, &stats_sample, self.diff_params);
+        if let Some(bands) = &self.diff_params.acceptance_bands {
+            // the bands replace the threshold, as in ContinuousAuthenticator
+            let fraction = if overlap {
+                KeynomeAuthenticator::compute_band_fraction(self.stats, &stats_sample, self.diff_params, bands.width)
+            } else {
+                None
+            };
+            let accepted = fraction.is_some_and(|fraction| fraction >= bands.min_fraction);
+            return AuthResult { accepted, diff, threshold, n_comparisons, confidence: fraction.unwrap_or(0.0) };
+        }
+        let decidable = n_comparisons > 0 && overlap;
+        let confidence = if decidable && threshold > 0.0 { 1.0 - (diff / threshold).min(1.0) } else { 0.0 };
+        AuthResult { accepted: decidable && diff <= threshold, diff, threshold, n_comparisons, confidence }
+    }
+}
+
+// provenance of a stored profile
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProfileMetadata {
+    #[serde(default)]
+    pub created_at_ms: u64,
+    #[serde(default)]
+    pub tool_version: String,
+    #[serde(default)]
+    pub user_label: Option<String>,
+}
+
+impl ProfileMetadata {
+    #[cfg(feature = "std")]
+    pub fn new(user_label: Option<&str>) -> ProfileMetadata {
+        ProfileMetadata {
+            created_at_ms: SystemClock.now_ms() as u64,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            user_label: user_label.map(|s| s.to_string()),
+        }
+    }
+}
+
+// leading bytes of a passphrase-encrypted profile file
+pub const ENCRYPTED_PROFILE_MAGIC: &[u8] = b"KEYNOME-ENC1";
+
+// number of digraphs kept in a signature
+pub const SIGNATURE_SIZE: usize = 8;
+
+// compact screening summary of a profile: the means of its most stable digraphs, rounded
+// to whole ms. Only ASCII digraphs are used, so that every entry fits in 4 bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub entries: Vec<(Digraph, u16)>,
+}
+
+impl Signature {
+    // per entry: both keys and the mean as a little-endian u16
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 * self.entries.len());
+        for ((k1, k2), mean) in self.entries.iter() {
+            bytes.push(*k1 as u8);
+            bytes.push(*k2 as u8);
+            bytes.extend_from_slice(&mean.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Signature> {
+        let chunks = bytes.chunks_exact(4);
+        if !chunks.remainder().is_empty() {
+            return None;
+        }
+        let mut entries = Vec::new();
+        for c in chunks {
+            if !c[0].is_ascii() || !c[1].is_ascii() {
+                return None;
+            }
+            entries.push(((c[0] as char, c[1] as char), u16::from_le_bytes([c[2], c[3]])));
+        }
+        Some(Signature { entries })
+    }
+}
+
+// cheap pre-filter before the full diff: mean absolute difference in ms over the digraphs
+// both signatures hold, None when they share none
+pub fn signature_distance(a: &Signature, b: &Signature) -> Option<f64> {
+    let shared: Vec<f64> = a.entries.iter()
+        .filter_map(|(k, mean_a)| b.entries.iter()
+            .find(|(kb, _)| kb == k)
+            .map(|(_, mean_b)| (*mean_a as f64 - *mean_b as f64).abs()))
+        .collect();
+    if shared.is_empty() {
+        return None;
+    }
+    Some(shared.iter().sum::<f64>() / shared.len() as f64)
+}
+
+// on-disk layout of a profile. Older versions stored every field, and every stats entry,
+// as a JSON string nested in the object; UserProfile::deserialize still reads those.
+#[derive(Serialize, Deserialize)]
+struct ProfileDocument {
+    n_profile: u32,
+    n_sample: u32,
+    diff_base: f64,
+    diff_params: KeynomeAuthenticatorDiffParams,
+    stats: HashMap<String, DigraphStats>,
+    #[serde(default)]
+    genuine_diffs: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<ProfileMetadata>,
+    #[serde(default)]
+    frozen: bool,
+}
+
+pub struct UserProfile {
+    pub n_profile: u32,
+    pub n_sample: u32,
+    pub diff_base: f64,
+    pub diff_params: KeynomeAuthenticatorDiffParams,
+    pub stats: HashMap<Digraph, DigraphStats>,
+    // per-window genuine diffs observed at enrollment
+    pub genuine_diffs: Vec<f64>,
+    pub metadata: Option<ProfileMetadata>,
+    // curated baseline, update_with_sample leaves it untouched
+    pub frozen: bool,
+}
+
+impl UserProfile {
+    pub fn new(n_profile: u32, n_sample: u32, diff_base: f64,
+           _diff_params: &KeynomeAuthenticatorDiffParams,
+           _stats: &HashMap<Digraph, DigraphStats>) -> UserProfile {
+
+        let diff_params = (*_diff_params).clone();
+        let mut stats: HashMap<Digraph, DigraphStats> = HashMap::new();
+        for (k, v) in _stats.iter() {
+            stats.insert(*k, (*v).clone());
+        }
+        UserProfile { n_profile, n_sample, diff_base, diff_params, stats, genuine_diffs: Vec::new(), metadata: None, frozen: false }
+    }
+
+    // diffs of each complete n_sample window of a recorded session, skipping undecidable ones
+    pub fn window_diffs(&self, events: &VecDeque<KeyEvent>) -> Vec<f64> {
+        let n_sample = self.n_sample as usize;
+        if n_sample == 0 {
+            return Vec::new();
+        }
+
+        extract_windows(events, events.len(), n_sample, n_sample).iter()
+            .filter_map(|window| {
+                KeynomeAuthenticator::compute_diff_checked(&self.stats, &digraph_statistics_of(window), &self.diff_params)
+            })
+            .collect()
+    }
+
+    // bounds the profile size: keeps the `max_digraphs` digraphs with the most instances,
+    // ties broken by digraph so the result is deterministic. The diff base is not recomputed,
+    // see KeynomeAuthenticator::compute_diff_base_detailed_truncated.
+    pub fn truncate_digraphs(&mut self, max_digraphs: usize) {
+        truncate_digraph_statistics(&mut self.stats, max_digraphs);
+    }
+
+    // the SIGNATURE_SIZE comparable digraphs with the lowest std, ties broken by digraph
+    pub fn signature(&self) -> Signature {
+        let mut stable: Vec<(&Digraph, &DigraphStats)> = self.stats.iter()
+            .filter(|(k, v)| k.0.is_ascii() && k.1.is_ascii() && v.size_samples >= self.diff_params.min_instances as usize)
+            .collect();
+        stable.sort_by(|(ka, va), (kb, vb)| va.std.partial_cmp(&vb.std).unwrap().then(ka.cmp(kb)));
+        let entries = stable.iter()
+            .take(SIGNATURE_SIZE)
+            .map(|(k, v)| (**k, v.mean.round().clamp(0.0, u16::MAX as f64) as u16))
+            .collect();
+        Signature { entries }
+    }
+
+    pub fn authenticator(&self, multiplier: f64) -> KeynomeAuthenticator<'_, '_> {
+        KeynomeAuthenticator::new(self.diff_base, multiplier, &self.diff_params, &self.stats)
+            .with_n_sample(self.n_sample as usize)
+    }
+
+    pub fn set_genuine_diffs(&mut self, genuine_diffs: &[f64]) {
+        self.genuine_diffs = genuine_diffs.to_vec();
+    }
+
+    // distribution of the stored genuine diffs, needs at least two windows
+    pub fn genuine_diff_stats(&self) -> Option<DigraphStats> {
+        if self.genuine_diffs.len() < 2 {
+            return None;
+        }
+        let mean = mean(&self.genuine_diffs);
+        let std = standard_deviation(&self.genuine_diffs, mean);
+        Some(DigraphStats::new(self.genuine_diffs.len(), mean, std))
+    }
+
+    pub fn serialize(&self) -> String {
+        let document = ProfileDocument {
+            n_profile: self.n_profile,
+            n_sample: self.n_sample,
+            diff_base: self.diff_base,
+            diff_params: self.diff_params.clone(),
+            stats: self.stats.iter().map(|(k, v)| (format_digraph_key(k), v.clone())).collect(),
+            genuine_diffs: self.genuine_diffs.clone(),
+            metadata: self.metadata.clone(),
+            frozen: self.frozen,
+        };
+
+        serde_json::to_string(&document).unwrap()
+    }
+
+    // fraction of the comparable digraphs backed by no more than min_instances samples;
+    // near 1.0 the profile is too thin and will reject genuine variation
+    pub fn overfit_score(&self) -> f64 {
+        let min_instances = self.diff_params.min_instances as usize;
+        let counts: Vec<usize> = self.stats.values()
+            .map(|v| v.size_samples)
+            .filter(|n| *n >= min_instances)
+            .collect();
+        if counts.is_empty() {
+            return 1.0;
+        }
+        counts.iter().filter(|n| **n == min_instances).count() as f64 / counts.len() as f64
+    }
+
+    // compact overview of a profile without the full stats table
+    pub fn summary_json(&self) -> String {
+        let n_extremes = 5;
+
+        let mut sorted: Vec<(&Digraph, &DigraphStats)> = self.stats.iter().collect();
+        sorted.sort_by(|a, b| b.1.size_samples.cmp(&a.1.size_samples).then(a.0.cmp(b.0)));
+
+        let to_entry = |(k, v): &(&Digraph, &DigraphStats)| {
+            serde_json::json!({ "digraph": format_digraph_key(k), "size_samples": v.size_samples })
+        };
+        let top: Vec<serde_json::Value> = sorted.iter().take(n_extremes).map(to_entry).collect();
+        let bottom: Vec<serde_json::Value> = sorted.iter().rev().take(n_extremes).map(to_entry).collect();
+
+        // fraction of stored digraphs which are eligible for comparison
+        let n_eligible = self.stats.values()
+            .filter(|v| v.size_samples >= self.diff_params.min_instances as usize)
+            .count();
+        let coverage = if self.stats.is_empty() { 0.0 } else { n_eligible as f64 / self.stats.len() as f64 };
+
+        let summary = serde_json::json!({
+            "n_profile": self.n_profile,
+            "n_sample": self.n_sample,
+            "diff_base": self.diff_base,
+            "diff_params": self.diff_params,
+            "n_digraphs": self.stats.len(),
+            "coverage": coverage,
+            "overfit_score": self.overfit_score(),
+            "top_sampled": top,
+            "bottom_sampled": bottom,
+            "genuine_diffs": self.genuine_diff_stats(),
+            "metadata": self.metadata,
+            "frozen": self.frozen,
+        });
+        summary.to_string()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn to_compressed_bytes(&self) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(self.serialize().as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_compressed_bytes(bytes: &[u8]) -> Result<UserProfile, KeynomeError> {
+        let mut decoder = GzDecoder::new(bytes);
+        let mut serialized = String::new();
+        decoder.read_to_string(&mut serialized).map_err(|e| KeynomeError::Io(e.to_string()))?;
+        Ok(UserProfile::try_deserialize(&serialized)?)
+    }
+
+    // passphrase-protected profile: ENCRYPTED_PROFILE_MAGIC, a 16-byte salt and a 12-byte
+    // nonce, then the ChaCha20-Poly1305 ciphertext of serialize(). The key is derived from
+    // the passphrase with Argon2id; salt and nonce are fresh for every call.
+    #[cfg(feature = "encryption")]
+    pub fn to_encrypted_bytes(&self, passphrase: &str) -> Result<Vec<u8>, KeynomeError> {
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        getrandom::getrandom(&mut salt).map_err(|e| KeynomeError::Io(e.to_string()))?;
+        getrandom::getrandom(&mut nonce).map_err(|e| KeynomeError::Io(e.to_string()))?;
+
+        let cipher = UserProfile::profile_cipher(passphrase, &salt)?;
+        let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), self.serialize().as_bytes())
+            .map_err(|_| KeynomeError::Decryption("encryption failed".to_string()))?;
+
+        let mut bytes = ENCRYPTED_PROFILE_MAGIC.to_vec();
+        bytes.extend_from_slice(&salt);
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(bytes)
+    }
+
+    #[cfg(feature = "encryption")]
+    pub fn from_encrypted_bytes(bytes: &[u8], passphrase: &str) -> Result<UserProfile, KeynomeError> {
+        let header_len = ENCRYPTED_PROFILE_MAGIC.len() + 16 + 12;
+        if !UserProfile::is_encrypted(bytes) || bytes.len() < header_len {
+            return Err(KeynomeError::Decryption("not an encrypted profile".to_string()));
+        }
+        let salt = &bytes[ENCRYPTED_PROFILE_MAGIC.len()..ENCRYPTED_PROFILE_MAGIC.len() + 16];
+        let nonce = &bytes[header_len - 12..header_len];
+
+        let cipher = UserProfile::profile_cipher(passphrase, salt)?;
+        let plaintext = cipher.decrypt(Nonce::from_slice(nonce), &bytes[header_len..])
+            .map_err(|_| KeynomeError::Decryption("wrong passphrase or corrupted profile".to_string()))?;
+        let serialized = String::from_utf8(plaintext).map_err(|e| KeynomeError::Parse(e.to_string()))?;
+        Ok(UserProfile::try_deserialize(&serialized)?)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn profile_cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305, KeynomeError> {
+        let mut key = [0u8; 32];
+        argon2::Argon2::default().hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| KeynomeError::Decryption(e.to_string()))?;
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    pub fn is_encrypted(bytes: &[u8]) -> bool {
+        bytes.starts_with(ENCRYPTED_PROFILE_MAGIC)
+    }
+
+    // blends sample statistics into the profile with an exponential moving average;
+    // digraphs new to the profile are added as they are
+    pub fn update_with_sample(&mut self, stats_sample: &HashMap<Digraph, DigraphStats>, learning_rate: f64) {
+        if self.frozen {
+            return;
+        }
+        let rate = learning_rate.clamp(0.0, 1.0);
+        for (k, vs) in stats_sample.iter() {
+            match self.stats.get_mut(k) {
+                Some(v) => {
+                    let delta = vs.mean - v.mean;
+                    let var = (1.0 - rate) * v.std.powi(2) + rate * vs.std.powi(2) + rate * (1.0 - rate) * delta.powi(2);
+                    v.mean += rate * delta;
+                    v.std = var.sqrt();
+                    if v.has_quantiles() && vs.has_quantiles() {
+                        v.median += rate * (vs.median - v.median);
+                        v.iqr += rate * (vs.iqr - v.iqr);
+                    }
+                    v.extend_range(vs);
+                    v.size_samples += vs.size_samples;
+                    v.last_updated_ms = v.last_updated_ms.max(vs.last_updated_ms);
+                },
+                None => {
+                    self.stats.insert(*k, vs.clone());
+                },
+            }
+        }
+    }
+
+    // combines profiles enrolled in separate sessions: statistics are pooled and diff_base is
+    // weighted by each side's n_profile. Both sides need the same n_sample, since diff_base
+    // depends on the window size; diff params and metadata are taken from `self`. A frozen
+    // profile is a baseline that must not change, so neither side may be frozen.
+    pub fn merge(&self, other: &UserProfile) -> Result<UserProfile, KeynomeError> {
+        self.merge_with_samples(other, None, None)
+    }
+
+    // as merge, with the raw intervals of each side's enrollment (compute_digraph_samples);
+    // digraphs sampled on both sides are then pooled exactly, see merge_digraph_statistics
+    pub fn merge_with_samples(&self, other: &UserProfile, samples_self: Option<&HashMap<Digraph, Vec<f64>>>,
+                              samples_other: Option<&HashMap<Digraph, Vec<f64>>>) -> Result<UserProfile, KeynomeError> {
+        if self.n_sample != other.n_sample {
+            return Err(KeynomeError::InvalidProfile(
+                format!("n_sample differs ({} and {})", self.n_sample, other.n_sample)));
+        }
+        if self.frozen || other.frozen {
+            return Err(KeynomeError::InvalidProfile("a frozen profile can't be merged".to_string()));
+        }
+        let n_profile = self.n_profile + other.n_profile;
+        let diff_base = if n_profile == 0 {
+            (self.diff_base + other.diff_base) / 2.0
+        } else {
+            (self.diff_base * self.n_profile as f64 + other.diff_base * other.n_profile as f64) / n_profile as f64
+        };
+        let mut merged = UserProfile::new(n_profile, self.n_sample, diff_base, &self.diff_params,
+                                          &merge_digraph_statistics(&self.stats, &other.stats, samples_self, samples_other));
+        merged.genuine_diffs = [self.genuine_diffs.as_slice(), other.genuine_diffs.as_slice()].concat();
+        merged.metadata = self.metadata.clone();
+        Ok(merged)
+    }
+
+    // update_with_sample from freshly accepted events; the learning rate is clamped to [0, 1]
+    pub fn adapt(&mut self, events: &VecDeque<KeyEvent>, learning_rate: f64) {
+        self.update_with_sample(&digraph_statistics_of(events), learning_rate);
+    }
+
+    pub fn validate(&self) -> Result<(), KeynomeError> {
+        if self.n_sample == 0 {
+            return Err(KeynomeError::InvalidProfile("n_sample is zero".to_string()));
+        } else if self.n_sample > self.n_profile {
+            return Err(KeynomeError::InvalidProfile(
+                format!("n_sample ({}) exceeds n_profile ({})", self.n_sample, self.n_profile)));
+        } else if self.diff_base.is_nan() || self.diff_base <= 0.0 || self.diff_base.is_infinite() {
+            return Err(KeynomeError::InvalidProfile(
+                format!("diff_base ({}) is not a positive number", self.diff_base)));
+        } else if self.stats.is_empty() {
+            return Err(KeynomeError::InvalidProfile("stats are empty".to_string()));
+        } else if self.diff_params.frequency_weighting == FrequencyWeighting::Table
+            && !self.diff_params.frequency_table.as_ref().is_some_and(|table| table.values().any(|f| *f > 0.0)) {
+            return Err(KeynomeError::InvalidProfile("frequency_weighting is Table without a positive frequency_table".to_string()));
+        }
+        Ok(())
+    }
+
+    // panics on malformed input, see try_deserialize
+    pub fn deserialize(serialized: &str) -> UserProfile {
+        UserProfile::try_deserialize(serialized).unwrap()
+    }
+
+    // reads both the current and the legacy layout
+    pub fn try_deserialize(serialized: &str) -> Result<UserProfile, ProfileError> {
+        if UserProfile::is_legacy_format(serialized) {
+            return UserProfile::deserialize_legacy(serialized);
+        }
+
+        let value: serde_json::Value = serde_json::from_str(serialized).map_err(ProfileError::from)?;
+        for name in ["n_profile", "n_sample", "diff_base", "diff_params", "stats"].iter() {
+            match value.get(name) {
+                None => return Err(ProfileError::MissingField(name.to_string())),
+                Some(v) if *name != "diff_params" && *name != "stats" && !v.is_number() =>
+                    return Err(ProfileError::InvalidNumber(name.to_string(), v.to_string())),
+                _ => (),
+            }
+        }
+
+        let document: ProfileDocument = serde_json::from_value(value).map_err(ProfileError::from)?;
+        let mut stats: HashMap<Digraph, DigraphStats> = HashMap::new();
+        for (k, v) in document.stats {
+            let digraph = parse_digraph_key(&k).ok_or(ProfileError::InvalidDigraphKey(k.clone()))?;
+            stats.insert(digraph, v);
+        }
+
+        Ok(UserProfile {
+            n_profile: document.n_profile,
+            n_sample: document.n_sample,
+            diff_base: document.diff_base,
+            diff_params: document.diff_params,
+            stats,
+            genuine_diffs: document.genuine_diffs,
+            metadata: document.metadata,
+            frozen: document.frozen,
+        })
+    }
+
+    // only the digraph statistics, as {"a-b": {...}, ...}, without any user's thresholds or
+    // params, e.g. for building a population model
+    pub fn serialize_stats_only(&self) -> String {
+        KeystrokeLogger::serialize_digraph_statistics(&self.stats)
+    }
+
+    // counterpart of serialize_stats_only, attaching new scalar params to the loaded stats;
+    // panics on malformed input, see try_from_serialized_stats
+    pub fn from_serialized_stats(n_profile: u32, n_sample: u32, diff_base: f64,
+                                 diff_params: &KeynomeAuthenticatorDiffParams, serialized_stats: &str) -> UserProfile {
+        UserProfile::try_from_serialized_stats(n_profile, n_sample, diff_base, diff_params, serialized_stats).unwrap()
+    }
+
+    pub fn try_from_serialized_stats(n_profile: u32, n_sample: u32, diff_base: f64, diff_params: &KeynomeAuthenticatorDiffParams,
+                                     serialized_stats: &str) -> Result<UserProfile, ProfileError> {
+        let stats = KeystrokeLogger::try_deserialize_digraph_statistics(serialized_stats)?;
+        Ok(UserProfile::new(n_profile, n_sample, diff_base, diff_params, &stats))
+    }
+
+    // whether `serialized` uses the old layout with every field stored as a JSON string
+    pub fn is_legacy_format(serialized: &str) -> bool {
+        match serde_json::from_str::<serde_json::Value>(serialized) {
+            Ok(value) => value["n_profile"].is_string(),
+            Err(_) => false,
+        }
+    }
+
+    fn deserialize_legacy(serialized: &str) -> Result<UserProfile, ProfileError> {
+        let parse_error = ProfileError::from;
+        let str_keyed_map: HashMap<String, String> = serde_json::from_str(serialized).map_err(parse_error)?;
+        let field = |name: &str| str_keyed_map.get(name)
+            .ok_or_else(|| ProfileError::MissingField(name.to_string()));
+        let number_error = |name: &str| ProfileError::InvalidNumber(name.to_string(), str_keyed_map[name].clone());
+
+        let n_profile: u32 = field("n_profile")?.parse().map_err(|_| number_error("n_profile"))?;
+        let n_sample: u32 = field("n_sample")?.parse().map_err(|_| number_error("n_sample"))?;
+        let diff_base: f64 = field("diff_base")?.parse().map_err(|_| number_error("diff_base"))?;
+        let diff_params: KeynomeAuthenticatorDiffParams = serde_json::from_str(field("diff_params")?).map_err(parse_error)?;
+
+        // entries are either stringified JSON objects or, in later files, plain objects
+        let stats = KeystrokeLogger::try_deserialize_digraph_statistics(field("stats")?)?;
+
+        let genuine_diffs: Vec<f64> = match str_keyed_map.get("genuine_diffs") {
+            Some(v) => serde_json::from_str(v).map_err(parse_error)?,
+            None => Vec::new(),
+        };
+        let metadata: Option<ProfileMetadata> = match str_keyed_map.get("metadata") {
+            Some(v) => Some(serde_json::from_str(v).map_err(parse_error)?),
+            None => None,
+        };
+        let frozen: bool = match str_keyed_map.get("frozen") {
+            Some(v) => v.parse().map_err(|_| ProfileError::Json(format!("frozen is not a boolean: {}", v)))?,
+            None => false,
+        };
+
+        Ok(UserProfile { n_profile, n_sample, diff_base, diff_params, stats, genuine_diffs, metadata, frozen })
+    }
+
+    // rewrites a profile file of the legacy layout in the current one, keeping the original
+    // next to it as <path>.legacy. The file is only touched when it parses and validates.
+    // Returns false when the file is already in the current layout. Plain JSON files only.
+    #[cfg(feature = "std")]
+    pub fn migrate_legacy(path: &str) -> Result<bool, KeynomeError> {
+        let io_error = |e: std::io::Error| KeynomeError::Io(e.to_string());
+        let serialized = std::fs::read_to_string(path).map_err(io_error)?;
+        if !UserProfile::is_legacy_format(&serialized) {
+            return Ok(false);
+        }
+
+        let profile = UserProfile::deserialize_legacy(&serialized)?;
+        profile.validate()?;
+        std::fs::write(format!("{}.legacy", path), &serialized).map_err(io_error)?;
+        std::fs::write(path, profile.serialize()).map_err(io_error)?;
+        Ok(true)
+    }
+}
+
+
+// nearest-template matching: keeps one stats map per enrollment window instead of a
+// single average, so that several distinct typing modes each have a close reference
+#[derive(Clone, Debug)]
+pub struct TemplateProfile {
+    pub templates: Vec<HashMap<Digraph, DigraphStats>>,
+    pub diff_params: KeynomeAuthenticatorDiffParams,
+}
+
+impl TemplateProfile {
+    // one template per complete n_sample window of the enrollment events
+    pub fn from_events(events: &VecDeque<KeyEvent>, n_sample: usize,
+                       diff_params: &KeynomeAuthenticatorDiffParams) -> TemplateProfile {
+        let templates = extract_windows(events, events.len(), n_sample, n_sample).iter()
+            .map(digraph_statistics_of)
+            .filter(|stats| !stats.is_empty())
+            .collect();
+        TemplateProfile { templates, diff_params: diff_params.clone() }
+    }
+
+    // diff against the nearest template, None when no template is comparable
+    pub fn compute_diff(&self, stats_sample: &HashMap<Digraph, DigraphStats>) -> Option<f64> {
+        self.templates.iter()
+            .filter_map(|template| KeynomeAuthenticator::compute_diff_checked(template, stats_sample, &self.diff_params))
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+    }
+}
+
+// cohort normalization: expresses a diff relative to how far a generic population
+// typer scores against the same profile, so 1.0 means "as different as the population"
+// and thresholds on the normalized value transfer across users
+#[derive(Clone, Debug)]
+pub struct PopulationNormalizer {
+    pub population_diff: f64,
+}
+
+impl PopulationNormalizer {
+    // None when the population model shares no digraph with the profile or matches it exactly
+    pub fn from_population(profile: &UserProfile, stats_population: &HashMap<Digraph, DigraphStats>)
+        -> Option<PopulationNormalizer> {
+        let population_diff = KeynomeAuthenticator::compute_diff_checked(
+            &profile.stats, stats_population, &profile.diff_params)?;
+        if population_diff <= 0.0 {
+            return None;
+        }
+        Some(PopulationNormalizer { population_diff })
+    }
+
+    pub fn normalize(&self, diff: f64) -> f64 {
+        diff / self.population_diff
+    }
+}
+
+// how identifying each digraph is for this user: a d-prime between the profile and the
+// population, (mean_user - mean_population) / sqrt((std_user² + std_population²) / 2),
+// with the pooled std floored at MIN_STD_MS. Sorted by |d'|, most distinctive first; only
+// digraphs with min_instances on both sides are scored.
+pub fn digraph_discriminability(profile: &UserProfile, stats_population: &HashMap<Digraph, DigraphStats>)
+    -> Vec<(Digraph, f64)> {
+    let min_instances = profile.diff_params.min_instances as usize;
+    let mut scores: Vec<(Digraph, f64)> = profile.stats.iter()
+        .filter(|(_, v)| v.size_samples >= min_instances)
+        .filter_map(|(k, v)| {
+            let vp = stats_population.get(k).filter(|vp| vp.size_samples >= min_instances)?;
+            let pooled_std = ((v.std.powi(2) + vp.std.powi(2)) / 2.0).sqrt().max(MIN_STD_MS);
+            Some((*k, (v.mean - vp.mean) / pooled_std))
+        })
+        .collect();
+    scores.sort_by(|(ka, da), (kb, db)| db.abs().partial_cmp(&da.abs()).unwrap().then(ka.cmp(kb)));
+    scores
+}
+
+// combines several normalized feature diffs into one score:
+//   flight      digraph diff relative to the profile's diff_base
+//   dwell       mean z-score of the per-key hold durations
+//   cadence     relative difference of the overall mean interval
+//   correction  absolute difference of the backspace rate
+// A zero weight disables a component; components the sample can't provide are left out
+// and the remaining weights renormalized.
+#[derive(Clone, Debug)]
+pub struct FeatureEnsemble {
+    pub flight_weight: f64,
+    pub dwell_weight: f64,
+    pub cadence_weight: f64,
+    pub correction_weight: f64,
+    dwell: HashMap<char, DigraphStats>,
+    correction_rate: Option<f64>,
+}
+
+impl FeatureEnsemble {
+    // reference dwell and correction behaviour from the enrollment events, all weights 1.0
+    pub fn from_enrollment(enrollment: &KeystrokeLogger) -> FeatureEnsemble {
+        FeatureEnsemble {
+            flight_weight: 1.0,
+            dwell_weight: 1.0,
+            cadence_weight: 1.0,
+            correction_weight: 1.0,
+            dwell: enrollment.compute_dwell_statistics(),
+            correction_rate: enrollment.compute_correction_rate(),
+        }
+    }
+
+    fn dwell_diff(&self, dwell_sample: &HashMap<char, DigraphStats>) -> Option<f64> {
+        let zs: Vec<f64> = self.dwell.iter()
+            .filter_map(|(k, v)| dwell_sample.get(k).map(|vs| (v.mean - vs.mean).abs() / v.std.max(MIN_STD_MS)))
+            .collect();
+        if zs.is_empty() {
+            return None;
+        }
+        Some(zs.iter().sum::<f64>() / zs.len() as f64)
+    }
+
+    pub fn score(&self, profile: &UserProfile, sample: &KeystrokeLogger) -> Option<f64> {
+        let stats_sample = sample.compute_digraph_statistics();
+        let mut components: Vec<(f64, f64)> = Vec::new();
+
+        if self.flight_weight > 0.0 && profile.diff_base > 0.0 {
+            if let Some(diff) = KeynomeAuthenticator::compute_diff_checked(&profile.stats, &stats_sample, &profile.diff_params) {
+                components.push((self.flight_weight, diff / profile.diff_base));
+            }
+        }
+        if self.dwell_weight > 0.0 {
+            if let Some(diff) = self.dwell_diff(&sample.compute_dwell_statistics()) {
+                components.push((self.dwell_weight, diff));
+            }
+        }
+        if self.cadence_weight > 0.0 {
+            if let (Some(mean_profile), Some(mean_sample)) = (mean_interval(&profile.stats), mean_interval(&stats_sample)) {
+                if mean_profile > 0.0 {
+                    components.push((self.cadence_weight, (mean_sample - mean_profile).abs() / mean_profile));
+                }
+            }
+        }
+        if self.correction_weight > 0.0 {
+            if let (Some(rate_profile), Some(rate_sample)) = (self.correction_rate, sample.compute_correction_rate()) {
+                components.push((self.correction_weight, (rate_sample - rate_profile).abs()));
+            }
+        }
+
+        if components.is_empty() {
+            return None;
+        }
+        let total_weight: f64 = components.iter().map(|(w, _)| w).sum();
+        Some(components.iter().map(|(w, c)| w * c).sum::<f64>() / total_weight)
+    }
+}
+
+// hard check which rejects a sample regardless of its score, e.g. failed liveness
+pub trait VetoRule {
+    fn name(&self) -> &str;
+    fn vetoes(&self, sample: &KeystrokeLogger) -> bool;
+
+    fn reason(&self) -> RejectReason {
+        RejectReason::LivenessFailed
+    }
+}
+
+// synthetic input: interval spread implausibly low for a human
+pub struct SyntheticTimingVeto {
+    pub min_interval_std_ms: f64,
+}
+
+impl VetoRule for SyntheticTimingVeto {
+    fn name(&self) -> &str {
+        "synthetic_timing"
+    }
+
+    fn vetoes(&self, sample: &KeystrokeLogger) -> bool {
+        let intervals: Vec<f64> = sample.ngraph_intervals::<2>(0).map(|(_, interval)| interval).collect();
+        intervals.len() >= 2 && standard_deviation(&intervals, mean(&intervals)) < self.min_interval_std_ms
+    }
+}
+
+// replayed input: `min_run` consecutive intervals repeat a recorded session (e.g. the
+// enrollment) to within `tolerance_us`, which nobody typing the same text again manages
+pub struct ReplayVeto {
+    recorded: Vec<(Digraph, u128)>,
+    pub min_run: usize,
+    pub tolerance_us: u128,
+}
+
+impl ReplayVeto {
+    pub fn new(recorded: &VecDeque<KeyEvent>, min_run: usize, tolerance_us: u128) -> ReplayVeto {
+        ReplayVeto { recorded: ReplayVeto::intervals(recorded), min_run, tolerance_us }
+    }
+
+    fn intervals(events: &VecDeque<KeyEvent>) -> Vec<(Digraph, u128)> {
+        events.iter().zip(events.iter().skip(1))
+            .map(|(ev1, ev2)| ((ev1.key, ev2.key), ev2.timestamp_us.saturating_sub(ev1.timestamp_us)))
+            .collect()
+    }
+}
+
+impl VetoRule for ReplayVeto {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    fn vetoes(&self, sample: &KeystrokeLogger) -> bool {
+        let intervals = ReplayVeto::intervals(sample.get_key_events());
+        let min_run = self.min_run.max(1);
+        intervals.windows(min_run).any(|run| {
+            self.recorded.windows(min_run).any(|recorded| {
+                run.iter().zip(recorded.iter())
+                    .all(|((k1, t1), (k2, t2))| k1 == k2 && t1.abs_diff(*t2) <= self.tolerance_us)
+            })
+        })
+    }
+
+    fn reason(&self) -> RejectReason {
+        RejectReason::ReplaySuspected
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyDecision {
+    pub verdict: AuthVerdict,
+    // soft score of the ensemble, None when vetoed or no feature was comparable
+    pub score: Option<f64>,
+    pub vetoed_by: Option<String>,
+}
+
+// hard rules first, any of them rejects outright; otherwise the soft features are
+// weighted into one ensemble score and compared with the threshold
+pub struct DecisionPolicy {
+    pub ensemble: FeatureEnsemble,
+    pub threshold: f64,
+    pub vetoes: Vec<Box<dyn VetoRule>>,
+}
+
+impl DecisionPolicy {
+    pub fn new(ensemble: FeatureEnsemble, threshold: f64) -> DecisionPolicy {
+        DecisionPolicy { ensemble, threshold, vetoes: Vec::new() }
+    }
+
+    pub fn add_veto(&mut self, rule: Box<dyn VetoRule>) {
+        self.vetoes.push(rule);
+    }
+
+    pub fn decide(&self, profile: &UserProfile, sample: &KeystrokeLogger) -> PolicyDecision {
+        if let Some(rule) = self.vetoes.iter().find(|rule| rule.vetoes(sample)) {
+            return PolicyDecision {
+                verdict: AuthVerdict::Reject(rule.reason()), score: None, vetoed_by: Some(rule.name().to_string()),
+            };
+        }
+
+        let score = self.ensemble.score(profile, sample);
+        let verdict = match score {
+            None => AuthVerdict::Insufficient,
+            Some(score) if score <= self.threshold => AuthVerdict::Accept,
+            Some(_) => AuthVerdict::Reject(RejectReason::DiffExceeded),
+        };
+        PolicyDecision { verdict, score, vetoed_by: None }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CrossValidationReport {
+    pub k: usize,
+    // genuine diffs of every held-out n_sample window, fold by fold
+    pub diffs: Vec<f64>,
+    pub mean: f64,
+    pub variance: f64,
+}
+
+impl CrossValidationReport {
+    // k-fold cross-validation over a single session: the events are cut into k contiguous
+    // folds, and each fold's windows are scored against a profile built from the others.
+    // None when k < 2 or no held-out window could be scored.
+    pub fn compute(events: &VecDeque<KeyEvent>, k: usize, n_sample: usize,
+                   diff_params: &KeynomeAuthenticatorDiffParams) -> Option<CrossValidationReport> {
+        if k < 2 || events.len() < k {
+            return None;
+        }
+
+        let fold_len = events.len() / k;
+        let folds: Vec<VecDeque<KeyEvent>> = (0..k)
+            .map(|i| events.range((i * fold_len)..((i + 1) * fold_len)).cloned().collect())
+            .collect();
+
+        let mut diffs: Vec<f64> = Vec::new();
+        for (i, fold) in folds.iter().enumerate() {
+            // the remaining folds are joined without forming digraphs across the gaps
+            let mut kstr = KeystrokeLogger::detached();
+            for (j, other) in folds.iter().enumerate() {
+                if j == i {
+                    continue;
+                }
+                kstr.add_digraph_break();
+                for ev in other {
+                    kstr.add_key_event(ev.clone());
+                }
+            }
+            let stats = kstr.compute_digraph_statistics();
+
+            for window in extract_windows(fold, fold.len(), n_sample, n_sample) {
+                if let Some(diff) = KeynomeAuthenticator::compute_diff_checked(
+                    &stats, &digraph_statistics_of(&window), diff_params) {
+                    diffs.push(diff);
+                }
+            }
+        }
+
+        if diffs.is_empty() {
+            return None;
+        }
+        let mean = diffs.iter().sum::<f64>() / diffs.len() as f64;
+        let variance = diffs.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / diffs.len() as f64;
+        Some(CrossValidationReport { k, diffs, mean, variance })
+    }
+}
+
+// decision latency against accuracy for one window size, see sweep()
+#[derive(Serialize, Clone, Debug)]
+pub struct WindowSizeTradeoff {
+    pub n_sample: usize,
+    // mean time span of a window, i.e. the wait until the first verdict
+    pub latency_ms: f64,
+    pub genuine_mean: f64,
+    pub genuine_std: f64,
+    pub impostor_mean: f64,
+    // (impostor_mean - genuine_mean) / genuine_std, higher separates better
+    pub separation: f64,
+}
+
+impl WindowSizeTradeoff {
+    // scores the non-overlapping windows of each size against a profile of the whole session.
+    // The impostor is synthetic: the same keys with the session's intervals shuffled.
+    // Sizes leaving fewer than two scorable windows are skipped.
+    pub fn sweep(events: &VecDeque<KeyEvent>, n_samples: &[usize], diff_params: &KeynomeAuthenticatorDiffParams,
+                 rng: &mut SeededRng) -> Vec<WindowSizeTradeoff> {
+        let stats = digraph_statistics_of(events);
+
+        let mut intervals: Vec<u128> = events.iter().zip(events.iter().skip(1))
+            .map(|(ev1, ev2)| ev2.timestamp_us.saturating_sub(ev1.timestamp_us))
+            .collect();
+        rng.shuffle(&mut intervals);
+        let mut impostor: VecDeque<KeyEvent> = VecDeque::new();
+        for (i, ev) in events.iter().enumerate() {
+            let timestamp_us = match impostor.back() {
+                Some(prev) => prev.timestamp_us + intervals[i - 1],
+                None => ev.timestamp_us,
+            };
+            impostor.push_back(KeyEvent::from_micros(timestamp_us, ev.key));
+        }
+
+        let diffs = |events: &VecDeque<KeyEvent>, n_sample: usize| -> Vec<f64> {
+            extract_windows(events, events.len(), n_sample, n_sample).iter()
+                .filter_map(|window| KeynomeAuthenticator::compute_diff_checked(&stats, &digraph_statistics_of(window), diff_params))
+                .collect()
+        };
+
+        let mut tradeoffs = Vec::new();
+        for &n_sample in n_samples {
+            let genuine = diffs(events, n_sample);
+            let impostor_diffs = diffs(&impostor, n_sample);
+            if genuine.len() < 2 || impostor_diffs.is_empty() {
+                continue;
+            }
+
+            let windows = extract_windows(events, events.len(), n_sample, n_sample);
+            let latency_ms = windows.iter()
+                .map(|w| span_ms(w.front().unwrap().timestamp_us, w.back().unwrap().timestamp_us))
+                .sum::<f64>() / windows.len() as f64;
+            let genuine_mean = mean(&genuine);
+            let genuine_std = standard_deviation(&genuine, genuine_mean);
+            let impostor_mean = mean(&impostor_diffs);
+
+            tradeoffs.push(WindowSizeTradeoff {
+                n_sample,
+                latency_ms,
+                genuine_mean,
+                genuine_std,
+                impostor_mean,
+                separation: (impostor_mean - genuine_mean) / genuine_std.max(f64::EPSILON),
+            });
+        }
+        tradeoffs
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct EvaluationReport {
+    pub threshold: f64,
+    pub n_genuine: usize,
+    pub n_impostor: usize,
+    // impostor windows accepted / genuine windows rejected at the threshold
+    pub far: f64,
+    pub frr: f64,
+    // rate where FAR and FRR meet over every candidate threshold, and that threshold
+    pub eer: f64,
+    pub eer_threshold: f64,
+}
+
+impl EvaluationReport {
+    // None unless both genuine and impostor diffs are given
+    pub fn compute(genuine_diffs: &[f64], impostor_diffs: &[f64], threshold: f64) -> Option<EvaluationReport> {
+        if genuine_diffs.is_empty() || impostor_diffs.is_empty() {
+            return None;
+        }
+
+        let rates = |t: f64| {
+            let far = impostor_diffs.iter().filter(|d| **d <= t).count() as f64 / impostor_diffs.len() as f64;
+            let frr = genuine_diffs.iter().filter(|d| **d > t).count() as f64 / genuine_diffs.len() as f64;
+            (far, frr)
+        };
+
+        let mut candidates: Vec<f64> = genuine_diffs.iter().chain(impostor_diffs.iter()).cloned().collect();
+        candidates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut eer = f64::INFINITY;
+        let mut eer_threshold = threshold;
+        let mut best_gap = f64::INFINITY;
+        for t in candidates {
+            let (far, frr) = rates(t);
+            if (far - frr).abs() < best_gap {
+                best_gap = (far - frr).abs();
+                eer = (far + frr) / 2.0;
+                eer_threshold = t;
+            }
+        }
+
+        let (far, frr) = rates(threshold);
+        Some(EvaluationReport {
+            threshold,
+            n_genuine: genuine_diffs.len(),
+            n_impostor: impostor_diffs.len(),
+            far,
+            frr,
+            eer,
+            eer_threshold,
+        })
+    }
+}
+
+// small deterministic PRNG (SplitMix64) shared by every randomized helper, so that
+// runs with the same seed are reproducible bit-for-bit across platforms and versions
+#[derive(Clone, Debug)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> SeededRng {
+        SeededRng { state: seed }
+    }
+
+    // seeds from the system clock when no seed is given
+    #[cfg(feature = "std")]
+    pub fn from_seed(seed: Option<u64>) -> SeededRng {
+        match seed {
+            Some(seed) => SeededRng::new(seed),
+            None => SeededRng::new(SystemClock.now_ms() as u64),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // uniform in [0, n)
+    pub fn next_below(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize
+    }
+
+    // standard normal via Box-Muller
+    pub fn next_gaussian(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        (u1.ln() * -2.0).sqrt() * (2.0 * core::f64::consts::PI * u2).cos()
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.next_below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+// timing noise applied to recorded events before they're scored, for robustness testing
+pub trait NoiseModel {
+    fn perturb_ms(&mut self, timestamp_ms: u128) -> u128;
+}
+
+// gaussian jitter with the given std, clamped at 0
+pub struct Jitter {
+    pub std_ms: f64,
+    pub rng: SeededRng,
+}
+
+impl NoiseModel for Jitter {
+    fn perturb_ms(&mut self, timestamp_ms: u128) -> u128 {
+        (timestamp_ms as f64 + self.std_ms * self.rng.next_gaussian()).round().max(0.0) as u128
+    }
+}
+
+// timestamps truncated to multiples of `step_ms`, like a coarse clock
+pub struct Granularity {
+    pub step_ms: u128,
+}
+
+impl NoiseModel for Granularity {
+    fn perturb_ms(&mut self, timestamp_ms: u128) -> u128 {
+        if self.step_ms == 0 {
+            return timestamp_ms;
+        }
+        timestamp_ms - timestamp_ms % self.step_ms
+    }
+}
+
+// uniformly random extra delay of up to `max_ms`, as from a laggy input stack or network
+pub struct RandomDelay {
+    pub max_ms: u64,
+    pub rng: SeededRng,
+}
+
+impl NoiseModel for RandomDelay {
+    fn perturb_ms(&mut self, timestamp_ms: u128) -> u128 {
+        timestamp_ms + self.rng.next_below(self.max_ms as usize + 1) as u128
+    }
+}
+
+// press and release times are perturbed independently, so events may come out of order
+pub fn apply_noise(events: &VecDeque<KeyEvent>, model: &mut dyn NoiseModel) -> VecDeque<KeyEvent> {
+    events.iter()
+        .map(|ev| {
+            let mut noisy = ev.clone();
+            // the sub-millisecond part is kept as is
+            let perturb_us = |model: &mut dyn NoiseModel, us: u128| model.perturb_ms(us / 1000) * 1000 + us % 1000;
+            noisy.timestamp_us = perturb_us(model, ev.timestamp_us);
+            noisy.release_us = ev.release_us.map(|us| perturb_us(model, us));
+            noisy
+        })
+        .collect()
+}
+
+// P-square streaming quantile estimator (Jain & Chlamtac), O(1) memory
+#[derive(Clone, Debug)]
+pub struct P2Quantile {
+    p: f64,
+    count: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    pub fn new(p: f64) -> P2Quantile {
+        let p = p.clamp(0.0, 1.0);
+        P2Quantile {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [0.0, 1.0, 2.0, 3.0, 4.0],
+            desired: [0.0, 2.0 * p, 4.0 * p, 2.0 + 2.0 * p, 4.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn observe(&mut self, x: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            }
+            return;
+        }
+        self.count += 1;
+
+        let k = if x < self.heights[0] {
+            self.heights[0] = x;
+            0
+        } else if x >= self.heights[4] {
+            self.heights[4] = x;
+            3
+        } else {
+            (0..4).find(|&i| x < self.heights[i + 1]).unwrap()
+        };
+
+        for i in (k + 1)..5 {
+            self.positions[i] += 1.0;
+        }
+        for i in 0..5 {
+            self.desired[i] += self.increments[i];
+        }
+
+        // adjust the three middle markers
+        for i in 1..4 {
+            let d = self.desired[i] - self.positions[i];
+            if (d >= 1.0 && self.positions[i + 1] - self.positions[i] > 1.0)
+                || (d <= -1.0 && self.positions[i - 1] - self.positions[i] < -1.0) {
+                let d = d.signum();
+                let q = self.parabolic(i, d);
+                self.heights[i] = if self.heights[i - 1] < q && q < self.heights[i + 1] { q } else { self.linear(i, d) };
+                self.positions[i] += d;
+            }
+        }
+    }
+
+    fn parabolic(&self, i: usize, d: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + d / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+               + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, d: f64) -> f64 {
+        let j = if d > 0.0 { i + 1 } else { i - 1 };
+        self.heights[i] + d * (self.heights[j] - self.heights[i]) / (self.positions[j] - self.positions[i])
+    }
+
+    pub fn estimate(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        } else if self.count <= 5 {
+            let mut sorted = self.heights[..self.count].to_vec();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let idx = ((self.count - 1) as f64 * self.p).round() as usize;
+            return Some(sorted[idx]);
+        }
+        Some(self.heights[2])
+    }
+}
+
+// threshold tracking a percentile of recently accepted diffs, plus a relative margin
+#[derive(Clone, Debug)]
+pub struct AdaptiveThreshold {
+    pub margin: f64,
+    pub min_observations: usize,
+    tracker: P2Quantile,
+}
+
+impl AdaptiveThreshold {
+    pub fn new(quantile: f64, margin: f64, min_observations: usize) -> AdaptiveThreshold {
+        AdaptiveThreshold { margin, min_observations, tracker: P2Quantile::new(quantile) }
+    }
+
+    pub fn observe(&mut self, diff: f64) {
+        self.tracker.observe(diff);
+    }
+
+    pub fn threshold(&self) -> Option<f64> {
+        if self.tracker.count() < self.min_observations {
+            return None;
+        }
+        self.tracker.estimate().map(|q| q * (1.0 + self.margin))
+    }
+}
+
+// key decision values for plotting how an enrollment sits relative to the threshold
+#[derive(Serialize, Clone, Debug)]
+pub struct DecisionBoundary {
+    pub diff_base: f64,
+    pub threshold: f64,
+    pub genuine_mean: Option<f64>,
+    pub genuine_std: Option<f64>,
+    // simulated diffs from 0 to twice the threshold, with whether each would be accepted
+    pub sweep: Vec<(f64, bool)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RejectReason {
+    // the diff (or the fraction of digraphs outside their acceptance band) is beyond the threshold
+    DiffExceeded,
+    // the input repeats recorded timing, e.g. ReplayVeto
+    ReplaySuspected,
+    // the input doesn't look human, e.g. SyntheticTimingVeto
+    LivenessFailed,
+    // the window covers less than min_overlap_ratio of the profile
+    InsufficientOverlap,
+    // most digraphs of the window never occur in the profile, as after switching keyboard
+    // layouts; the host should ask the user to check the layout rather than lock them out
+    LayoutChanged,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuthVerdict {
+    Accept,
+    Reject(RejectReason),
+    // not enough keystrokes buffered yet
+    Warming,
+    // the window shares no comparable digraph with the profile, so no decision can be made
+    Insufficient,
+}
+
+// how long evaluation is suspended after a rejection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cooldown {
+    Keystrokes(usize),
+    Millis(u128),
+}
+
+// scores a sliding window of the latest n_sample keystrokes against a profile
+pub struct ContinuousAuthenticator {
+    pub profile: UserProfile,
+    pub multiplier: f64,
+    pub adaptive_threshold: Option<AdaptiveThreshold>,
+    // reject as soon as the partial diff exceeds the threshold
+    pub early_exit: bool,
+    pub cooldown: Option<Cooldown>,
+    // novel digraph ratio above which a window is flagged as a possible layout change
+    pub layout_change_ratio: Option<f64>,
+    // per digraph, how many decisions it was compared in; None unless tracking is enabled
+    comparison_counts: Option<HashMap<Digraph, u64>>,
+    // remaining keystrokes, or the timestamp until which the last rejection is held
+    cooldown_remaining: Option<Cooldown>,
+    cooldown_reason: RejectReason,
+    kstr: KeystrokeLogger,
+}
+
+impl ContinuousAuthenticator {
+    #[cfg(feature = "std")]
+    pub fn new(profile: UserProfile, multiplier: f64) -> ContinuousAuthenticator {
+        ContinuousAuthenticator::with_clock(profile, multiplier, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(profile: UserProfile, multiplier: f64, clock: Box<dyn Clock>) -> ContinuousAuthenticator {
+        let mut kstr = KeystrokeLogger::with_clock(clock);
+        kstr.set_events_limit(profile.n_sample as usize);
+        if profile.diff_params.normalize_shift {
+            kstr.set_key_normalizer(KeystrokeLogger::us_shift_normalizer());
+        }
+        ContinuousAuthenticator {
+            profile,
+            multiplier,
+            adaptive_threshold: None,
+            early_exit: false,
+            cooldown: None,
+            layout_change_ratio: None,
+            comparison_counts: None,
+            cooldown_remaining: None,
+            cooldown_reason: RejectReason::DiffExceeded,
+            kstr,
+        }
+    }
+
+    pub fn set_cooldown(&mut self, cooldown: Cooldown) {
+        self.cooldown = Some(cooldown);
+    }
+
+    // audit mode: counts, for every digraph, the diffs it contributed to from now on
+    pub fn set_comparison_tracking(&mut self, enabled: bool) {
+        self.comparison_counts = if enabled { Some(HashMap::new()) } else { None };
+    }
+
+    pub fn comparison_counts(&self) -> Option<&HashMap<Digraph, u64>> {
+        self.comparison_counts.as_ref()
+    }
+
+    pub fn set_adaptive_threshold(&mut self, adaptive_threshold: AdaptiveThreshold) {
+        self.adaptive_threshold = Some(adaptive_threshold);
+    }
+
+    pub fn get_key_events(&self) -> &VecDeque<KeyEvent> {
+        self.kstr.get_key_events()
+    }
+
+    pub fn threshold(&self) -> f64 {
+        let static_threshold = self.profile.diff_base * self.multiplier;
+        match &self.adaptive_threshold {
+            Some(adaptive) => adaptive.threshold().unwrap_or(static_threshold),
+            None => static_threshold,
+        }
+    }
+
+    pub fn decision_boundary(&self, n_points: usize) -> DecisionBoundary {
+        let threshold = self.threshold();
+        let genuine = self.profile.genuine_diff_stats();
+        let step = if n_points > 1 { 2.0 * threshold / (n_points - 1) as f64 } else { 0.0 };
+        let sweep = (0..n_points)
+            .map(|i| i as f64 * step)
+            .map(|diff| (diff, diff <= threshold))
+            .collect();
+
+        DecisionBoundary {
+            diff_base: self.profile.diff_base,
+            threshold,
+            genuine_mean: genuine.as_ref().map(|g| g.mean),
+            genuine_std: genuine.as_ref().map(|g| g.std),
+            sweep,
+        }
+    }
+
+    // whether the buffered window is full and shares enough digraphs with the
+    // profile for add_key_event to render an accept/reject verdict
+    pub fn is_ready(&self) -> bool {
+        if self.kstr.get_key_events().len() < self.profile.n_sample as usize {
+            return false;
+        }
+
+        let stats_sample = self.kstr.compute_digraph_statistics();
+        KeynomeAuthenticator::compute_diff_checked(&self.profile.stats, &stats_sample, &self.profile.diff_params).is_some()
+    }
+
+    // whether the last rejection is still held at the given time
+    fn in_cooldown(&mut self, timestamp_ms: u128) -> bool {
+        match self.cooldown_remaining {
+            Some(Cooldown::Keystrokes(n)) if n > 0 => {
+                self.cooldown_remaining = Some(Cooldown::Keystrokes(n - 1));
+                true
+            },
+            Some(Cooldown::Millis(until)) if timestamp_ms < until => true,
+            _ => {
+                self.cooldown_remaining = None;
+                false
+            },
+        }
+    }
+
+    // statistics of the currently buffered window, stamped with its latest event
+    pub fn window_statistics(&self) -> HashMap<Digraph, DigraphStats> {
+        let mut stats = self.kstr.compute_digraph_statistics();
+        if let Some(ev) = self.kstr.get_key_events().back() {
+            stamp_last_updated(&mut stats, ev.timestamp_ms() as u64);
+        }
+        stats
+    }
+
+    // fraction of the buffered digraph instances whose digraph the profile has never seen.
+    // An impostor on the same layout still mostly types known digraphs, whereas a layout
+    // switch remaps the keys and turns most of them novel at once.
+    pub fn novel_digraph_ratio(&self) -> Option<f64> {
+        let mut n_pairs = 0;
+        let mut n_novel = 0;
+        for [ev1, ev2] in self.kstr.windows(0) {
+            n_pairs += 1;
+            if !self.profile.stats.contains_key(&(ev1.key, ev2.key)) {
+                n_novel += 1;
+            }
+        }
+        if n_pairs == 0 {
+            return None;
+        }
+        Some(n_novel as f64 / n_pairs as f64)
+    }
+
+    // diff of whatever is buffered against the profile, None without enough overlap
+    pub fn current_diff(&self) -> Option<f64> {
+        let stats_sample = self.kstr.compute_digraph_statistics();
+        KeynomeAuthenticator::compute_diff_checked(&self.profile.stats, &stats_sample, &self.profile.diff_params)
+    }
+
+    // fallback for sessions ending before the window filled up: scores whatever is buffered
+    // (without cooldown or adaptation). The bool is true when the window was partial, in
+    // which case the verdict rests on fewer keystrokes than n_sample and is less reliable.
+    pub fn score_buffered(&self) -> (AuthVerdict, bool) {
+        let partial = self.kstr.get_key_events().len() < self.profile.n_sample as usize;
+        if let Some(bands) = &self.profile.diff_params.acceptance_bands {
+            return (self.band_verdict(bands, &self.kstr.compute_digraph_statistics()), partial);
+        }
+        let verdict = match self.current_diff() {
+            None => AuthVerdict::Insufficient,
+            Some(diff) if diff <= self.threshold() => AuthVerdict::Accept,
+            Some(_) => AuthVerdict::Reject(RejectReason::DiffExceeded),
+        };
+        (verdict, partial)
+    }
+
+    // one JSON line describing the buffered window, for forensic review of rejections.
+    // With `redact_content` only the timing survives: the digraph statistics are stored
+    // without their keys, sorted by mean, and the typed keys are left out.
+    pub fn forensic_record(&self, redact_content: bool) -> String {
+        let events = self.kstr.get_key_events();
+        let stats_sample = self.kstr.compute_digraph_statistics();
+        let diff = self.current_diff();
+
+        let mut record = serde_json::json!({
+            "timestamp_ms": events.back().map(|ev| ev.timestamp_ms() as u64),
+            "n_events": events.len(),
+            "diff": diff,
+            "threshold": self.threshold(),
+        });
+        if redact_content {
+            let mut timings: Vec<&DigraphStats> = stats_sample.values().collect();
+            timings.sort_by(|a, b| a.mean.partial_cmp(&b.mean).unwrap());
+            record["stats"] = serde_json::json!(timings);
+        } else {
+            let stats: HashMap<String, &DigraphStats> = stats_sample.iter()
+                .map(|(k, v)| (format_digraph_key(k), v))
+                .collect();
+            record["stats"] = serde_json::json!(stats);
+            record["keys"] = serde_json::json!(events.iter().map(|ev| ev.key).collect::<String>());
+        }
+        record.to_string()
+    }
+
+    // the next keystroke doesn't form a digraph with the previous one, see KeystrokeLogger::add_digraph_break
+    pub fn add_digraph_break(&mut self) {
+        self.kstr.add_digraph_break();
+    }
+
+    pub fn add_keystroke(&mut self, key: char) -> AuthVerdict {
+        let ts = self.kstr.clock.now_us();
+        self.add_key_event(KeyEvent::from_micros(ts, key))
+    }
+
+    pub fn add_key_event(&mut self, ev: KeyEvent) -> AuthVerdict {
+        let timestamp_ms = ev.timestamp_ms();
+        self.kstr.add_key_event(ev);
+        if self.kstr.get_key_events().len() < self.profile.n_sample as usize {
+            return AuthVerdict::Warming;
+        } else if self.in_cooldown(timestamp_ms) {
+            return AuthVerdict::Reject(self.cooldown_reason);
+        }
+
+        // a layout switch is no reason for a cooldown, the user only has to switch back
+        if let (Some(max_ratio), Some(ratio)) = (self.layout_change_ratio, self.novel_digraph_ratio()) {
+            if ratio > max_ratio {
+                return AuthVerdict::Reject(RejectReason::LayoutChanged);
+            }
+        }
+
+        let stats_sample = self.kstr.compute_digraph_statistics();
+        if !KeynomeAuthenticator::has_sufficient_overlap(&self.profile.stats, &stats_sample, &self.profile.diff_params) {
+            return self.reject(timestamp_ms, RejectReason::InsufficientOverlap);
+        }
+
+        if let Some(bands) = &self.profile.diff_params.acceptance_bands {
+            let verdict = self.band_verdict(bands, &stats_sample);
+            if let AuthVerdict::Reject(reason) = verdict {
+                return self.reject(timestamp_ms, reason);
+            }
+            return verdict;
+        }
+
+        let threshold = self.threshold();
+        let limit = if self.early_exit { threshold } else { f64::INFINITY };
+        let (diff, n_comparisons) = match &mut self.comparison_counts {
+            Some(counts) => KeynomeAuthenticator::compute_diff_visiting(
+                &self.profile.stats, &stats_sample, &self.profile.diff_params, limit,
+                &mut |k| *counts.entry(*k).or_default() += 1),
+            None => KeynomeAuthenticator::compute_diff_counted(
+                &self.profile.stats, &stats_sample, &self.profile.diff_params, limit),
+        };
+
+        if n_comparisons == 0 {
+            AuthVerdict::Insufficient
+        } else if diff <= threshold {
+            // only genuine-looking windows feed the running distribution
+            if let Some(adaptive) = &mut self.adaptive_threshold {
+                adaptive.observe(diff);
+            }
+            AuthVerdict::Accept
+        } else {
+            self.reject(timestamp_ms, RejectReason::DiffExceeded)
+        }
+    }
+
+    // starts the cooldown, during which further rejections repeat `reason`
+    fn reject(&mut self, timestamp_ms: u128, reason: RejectReason) -> AuthVerdict {
+        self.cooldown_remaining = match self.cooldown {
+            Some(Cooldown::Millis(ms)) => Some(Cooldown::Millis(timestamp_ms + ms)),
+            cooldown => cooldown,
+        };
+        self.cooldown_reason = reason;
+        AuthVerdict::Reject(reason)
+    }
+
+    fn band_verdict(&self, bands: &AcceptanceBands, stats_sample: &HashMap<Digraph, DigraphStats>) -> AuthVerdict {
+        match KeynomeAuthenticator::compute_band_fraction(
+            &self.profile.stats, stats_sample, &self.profile.diff_params, bands.width) {
+            None => AuthVerdict::Insufficient,
+            Some(fraction) if fraction >= bands.min_fraction => AuthVerdict::Accept,
+            Some(_) => AuthVerdict::Reject(RejectReason::DiffExceeded),
+        }
+    }
+}
+
+#[cfg(test)]
+mod testkit {
+    use super::*;
+    use core::cell::Cell;
+    use alloc::rc::Rc;
+
+    // clock which only moves when advanced, clones share the same time
+    #[derive(Clone)]
+    pub struct MockClock {
+        now: Rc<Cell<u128>>,
+    }
+
+    impl MockClock {
+        pub fn advance(&self, ms: u128) {
+            self.now.set(self.now.get() + ms);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now_ms(&self) -> u128 {
+            self.now.get()
+        }
+    }
+
+    pub fn mock_clock(start_ms: u128) -> MockClock {
+        MockClock { now: Rc::new(Cell::new(start_ms)) }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use super::testkit::*;
+
+    #[test]
+    fn keystroke_logger_instantiation() {
+        let _ = KeystrokeLogger::new();
+    }
+
+    #[test]
+    fn keystroke_logger_preserving_keystroke_history() {
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_keystroke('a');
+        kstr.add_keystroke('b');
+        kstr.add_keystroke('c');
+
+        let events = kstr.get_key_events();
+        let keystrokes: Vec<char> = events.iter().map(|e| e.key).collect();
+        assert_eq!(keystrokes, vec!['a', 'b', 'c']);
+    }
+
+    #[test]
+    fn keystroke_logger_events_limit() {
+        let mut kstr = KeystrokeLogger::new();
+        kstr.set_events_limit(123);
+
+        for _ in 0..200 {
+            kstr.add_keystroke('a');
+        }
+        assert_eq!(kstr.get_key_events().len(), 123);
+    }
+
+    #[test]
+    fn keystroke_logger_time_difference() {
+        let delays: Vec<u128> = vec![12, 34, 56];
+
+        let clock = mock_clock(10000);
+        let mut kstr = KeystrokeLogger::with_clock(Box::new(clock.clone()));
+        kstr.add_keystroke('a');
+        clock.advance(delays[0]);
+        kstr.add_keystroke('b');
+        clock.advance(delays[1]);
+        kstr.add_keystroke('c');
+        clock.advance(delays[2]);
+        kstr.add_keystroke('d');
+
+        let events = kstr.get_key_events();
+        assert_eq!(events[0].timestamp_ms(), 10000);
+        assert_eq!(events[1].timestamp_ms() - events[0].timestamp_ms(), delays[0]);
+        assert_eq!(events[2].timestamp_ms() - events[1].timestamp_ms(), delays[1]);
+        assert_eq!(events[3].timestamp_ms() - events[2].timestamp_ms(), delays[2]);
+    }
+
+    #[test]
+    fn keystroke_logger_digraph_statistics() {
+        let mut kstr = KeystrokeLogger::new();
+
+        // a-b digraphs, diffs = [1000, 2000, 3000], mean = 2000.0, std = 1000.0
+        // b-a digraphs, diffs = [1000, 1000], mean = 1000.0, std = 0.0
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(11000, 'b'));
+
+        kstr.add_key_event(KeyEvent::new(12000, 'a'));
+        kstr.add_key_event(KeyEvent::new(14000, 'b'));
+
+        kstr.add_key_event(KeyEvent::new(15000, 'a'));
+        kstr.add_key_event(KeyEvent::new(18000, 'b'));
+
+        // e-f digraphs, diffs = [500, 1000, 1500], mean = 1000.0, std = 500.0
+        // f-e digraphs, diffs = [500, 2000], mean = 1250.0, std = 1060.66
+        kstr.add_key_event(KeyEvent::new(20000, 'e'));
+        kstr.add_key_event(KeyEvent::new(20500, 'f'));
+
+        kstr.add_key_event(KeyEvent::new(21000, 'e'));
+        kstr.add_key_event(KeyEvent::new(22000, 'f'));
+
+        kstr.add_key_event(KeyEvent::new(24000, 'e'));
+        kstr.add_key_event(KeyEvent::new(25500, 'f'));
+
+        let stats = kstr.compute_digraph_statistics();
+
+        assert_numerically_similar!(0.01, stats[&('a', 'b')].mean, 2000.0);
+        assert_numerically_similar!(0.01, stats[&('a', 'b')].std, 1000.0);
+
+        assert_numerically_similar!(0.01, stats[&('b', 'a')].mean, 1000.0);
+        assert_numerically_similar!(0.01, stats[&('b', 'a')].std, 0.0);
+
+        assert_numerically_similar!(0.01, stats[&('e', 'f')].mean, 1000.0);
+        assert_numerically_similar!(0.01, stats[&('e', 'f')].std, 500.0);
+
+        assert_numerically_similar!(0.01, stats[&('f', 'e')].mean, 1250.0);
+        assert_numerically_similar!(0.01, stats[&('f', 'e')].std, 1060.66);
+    }
+
+    #[test]
+    fn keystroke_logger_digraph_statistics_serialization() {
+        let keystrokes = "The most likely way for the world to be destroyed, \
+                          most experts agree, is by accident.  That's where we come in \
+                          we're computer professionals.  We cause accidents. \
+                          - Nathaniel Borenstein";
+
+        let mut kstr = KeystrokeLogger::new();
+        for c in keystrokes.chars() {
+            kstr.add_keystroke(c);
+        }
+
+        let stats = kstr.compute_digraph_statistics();
+
+        let serialized = KeystrokeLogger::serialize_digraph_statistics(&stats);
+        let deserialized = KeystrokeLogger::deserialize_digraph_statistics(&serialized);
+
+        let mut keys_orig: Vec<String> = stats.keys().map(|d| format!("{}-{}", d.0, d.1)).collect();
+        let mut keys_new: Vec<String> = deserialized.keys().map(|d| format!("{}-{}", d.0, d.1)).collect();
+
+        keys_orig.sort();
+        keys_new.sort();
+        assert_eq!(keys_orig, keys_new);
+
+        assert_eq!(KeystrokeLogger::try_deserialize_digraph_statistics(r#"{"ab": {"size_samples": 2, "mean": 1.0, "std": 0.0}}"#).unwrap_err(),
+                   ProfileError::InvalidDigraphKey("ab".to_string()));
+        assert!(matches!(KeystrokeLogger::try_deserialize_digraph_statistics(r#"{"a-b": 1.0}"#), Err(ProfileError::Json(_))));
+    }
+
+    // 12 events alternating a and b, a profile of two 6-event samples
+    fn two_window_session() -> VecDeque<KeyEvent> {
+        let mut events = VecDeque::new();
+
+        // (Profile) For all 12 instances,
+
+        // a-b digraphs
+        // diffs = [1000, 2000, 3000, 500, 1000, 1500]
+        // mean = 1500.0, std = 894.4272
+
+        // b-a digraphs
+        // diffs = [1000, 1000, 2000, 500, 2000]
+        // mean = 1300.0, std = 670.8204
+
+        // (Sample 1) For first 6 instances,
+
+        // a-b digraphs
+        // diffs = [1000, 2000, 3000]
+        // mean = 2000.0, std = 1000.0
+
+        // b-a digraphs
+        // diffs = [1000, 1000]
+        // mean = 1000.0, std = 0
+
+        // (Sample 2) For last 6 instances,
+
+        // a-b digraphs
+        // diffs = [500, 1000, 1500]
+        // mean = 1000.0, std = 500.0
+
+        // b-a digraphs
+        // diffs = [500, 2000]
+        // mean = 1250.0, std = 1060.6602
+
+        events.push_back(KeyEvent::new(10000, 'a'));
+        events.push_back(KeyEvent::new(11000, 'b'));
+
+        events.push_back(KeyEvent::new(12000, 'a'));
+        events.push_back(KeyEvent::new(14000, 'b'));
+
+        events.push_back(KeyEvent::new(15000, 'a'));
+        events.push_back(KeyEvent::new(18000, 'b'));
+
+        events.push_back(KeyEvent::new(20000, 'a'));
+        events.push_back(KeyEvent::new(20500, 'b'));
+
+        events.push_back(KeyEvent::new(21000, 'a'));
+        events.push_back(KeyEvent::new(22000, 'b'));
+
+        events.push_back(KeyEvent::new(24000, 'a'));
+        events.push_back(KeyEvent::new(25500, 'b'));
+        events
+    }
+
+    #[test]
+    fn keynome_authenticator_compute_diff_base() {
+        let events = two_window_session();
+
+        let diff_params = KeynomeAuthenticatorDiffParams {
+            dispersion: false,
+            min_instances: 1,
+            max_comparisons: 100,
+            ..Default::default()
+        };
+
+        // Profile <=> Sample 1
+        // (a-b) abs(1500 - 2000) = 500
+        // (b-a) abs(1300 - 1000) = 300
+        // diff = 800
+
+        // Profile <=> Sample 2
+        // (a-b) abs(1500 - 1000) = 500
+        // (b-a) abs(1300 - 1250) = 50
+        // diff = 550
+
+        // diff_base = (800 + 550) / 2 = 675
+
+        let diff_base = KeynomeAuthenticator::compute_diff_base(&events, 12, 6, &diff_params).unwrap();
+        assert_eq!(diff_base, 675.0);
+
+        let diffs = KeynomeAuthenticator::compute_diff_base_detailed(&events, 12, 6, &diff_params).unwrap();
+        assert_eq!(diffs, vec![800.0, 550.0]);
+
+        // a profile truncated to its most frequent digraph, a-b, is calibrated on a-b alone
+        let diffs = KeynomeAuthenticator::compute_diff_base_detailed_truncated(&events, 12, 6, &diff_params, 1).unwrap();
+        assert_eq!(diffs, vec![500.0, 500.0]);
+    }
+
+    #[test]
+    fn keynome_authenticator_diff() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('b', 'c'), DigraphStats::new(10, 200.0, 20.0));
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(10, 130.0, 10.0));
+        stats_sample.insert(('b', 'c'), DigraphStats::new(10, 150.0, 20.0));
+        let diff_params = KeynomeAuthenticatorDiffParams { dispersion: true, ..Default::default() };
+
+        let auth = KeynomeAuthenticator::new(1.0, 1.5, &diff_params, &stats);
+        assert_eq!(auth.diff(&stats_sample), KeynomeAuthenticator::compute_diff(&stats, &stats_sample, &diff_params));
+        assert!(auth.diff(&stats_sample) > 0.0);
+    }
+
+    #[test]
+    fn keynome_authenticator_authenticate() {
+        let events = two_window_session();
+        let diff_params = KeynomeAuthenticatorDiffParams { min_instances: 1, ..Default::default() };
+        let stats = digraph_statistics_of(&events);
+        let sample_1: VecDeque<KeyEvent> = events.iter().take(6).cloned().collect();
+        let sample_2: VecDeque<KeyEvent> = events.iter().skip(6).cloned().collect();
+
+        // threshold 675: sample 1 (diff 800) is rejected, sample 2 (diff 550) accepted
+        let auth = KeynomeAuthenticator::new(675.0, 1.0, &diff_params, &stats).with_n_sample(6);
+        assert!(!auth.authenticate(&sample_1));
+        assert!(auth.authenticate(&sample_2));
+        let auth = KeynomeAuthenticator::new(675.0, 1.5, &diff_params, &stats).with_n_sample(6);
+        assert!(auth.authenticate(&sample_1));
+
+        let result = auth.authenticate_with_score(&sample_2);
+        assert_eq!((result.diff, result.threshold, result.n_comparisons), (550.0, 1012.5, 2));
+        assert_numerically_similar!(1e-9, result.confidence, 1.0 - 550.0 / 1012.5);
+        let result = KeynomeAuthenticator::new(675.0, 1.0, &diff_params, &stats).with_n_sample(6).authenticate_with_score(&sample_1);
+        assert_eq!((result.accepted, result.confidence), (false, 0.0));
+
+        // too short, or nothing comparable
+        assert!(!auth.authenticate(&sample_2.iter().take(5).cloned().collect()));
+        let strangers: VecDeque<KeyEvent> = (0..6).map(|i| KeyEvent::new(10000 + 100 * i, ['x', 'y'][i as usize % 2])).collect();
+        assert!(!auth.authenticate(&strangers));
+        assert_eq!(auth.authenticate_with_score(&strangers).n_comparisons, 0);
+        assert_eq!(auth.authenticate_with_score(&strangers).confidence, 0.0);
+    }
+
+    #[test]
+    fn user_profile_summary_json() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('b', 'c'), DigraphStats::new(3, 200.0, 20.0));
+        stats.insert(('c', 'd'), DigraphStats::new(1, 300.0, 0.0));
+
+        let diff_params = KeynomeAuthenticatorDiffParams {
+            dispersion: false,
+            min_instances: 2,
+            max_comparisons: 100,
+            ..Default::default()
+        };
+        let profile = UserProfile::new(100, 10, 50.0, &diff_params, &stats);
+
+        let summary: serde_json::Value = serde_json::from_str(&profile.summary_json()).unwrap();
+        assert_eq!(summary["n_digraphs"], 3);
+        assert_eq!(summary["top_sampled"][0]["digraph"], "a-b");
+        assert_eq!(summary["bottom_sampled"][0]["digraph"], "c-d");
+        assert_numerically_similar!(0.0001, summary["coverage"].as_f64().unwrap(), 2.0 / 3.0);
+        assert!(summary.get("stats").is_none());
+    }
+
+    #[test]
+    fn keynome_authenticator_compute_diff_with_weights() {
+        let mut stats_profile = HashMap::new();
+        stats_profile.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats_profile.insert(('b', 'c'), DigraphStats::new(10, 200.0, 10.0));
+
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(5, 110.0, 10.0));
+        stats_sample.insert(('b', 'c'), DigraphStats::new(5, 220.0, 10.0));
+
+        let weights = KeynomeAuthenticatorDiffParams::parse_digraph_weights(r#"{"a-b": 3.0}"#);
+        let diff_params = KeynomeAuthenticatorDiffParams {
+            weights: Some(weights),
+            ..Default::default()
+        };
+
+        // (a-b) 3.0 * 10 = 30, (b-c) 1.0 * 20 = 20
+        let diff = KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params);
+        assert_numerically_similar!(0.0001, diff, 50.0);
+
+        let serialized = serde_json::to_string(&diff_params).unwrap();
+        let deserialized: KeynomeAuthenticatorDiffParams = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.weights.unwrap()[&('a', 'b')], 3.0);
+
+        // malformed weights files are reported instead of panicking
+        assert_eq!(KeynomeAuthenticatorDiffParams::try_parse_digraph_weights(r#"{"ab": 3.0}"#),
+                   Err(ProfileError::InvalidDigraphKey("ab".to_string())));
+        assert!(KeynomeAuthenticatorDiffParams::try_parse_digraph_weights(r#"{"a-b": "#).is_err());
+    }
+
+    #[test]
+    fn keynome_authenticator_compute_diff_sample_min_instances() {
+        let mut stats_profile = HashMap::new();
+        stats_profile.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats_profile.insert(('b', 'c'), DigraphStats::new(10, 200.0, 10.0));
+
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(5, 110.0, 10.0));
+        stats_sample.insert(('b', 'c'), DigraphStats::new(2, 220.0, 10.0));
+
+        let mut diff_params = KeynomeAuthenticatorDiffParams {
+            min_instances: 2,
+            ..Default::default()
+        };
+        let diff = KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params);
+        assert_numerically_similar!(0.0001, diff, 30.0);
+
+        // (b-c) has too few sample instances to be trusted
+        diff_params.sample_min_instances = Some(3);
+        let diff = KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params);
+        assert_numerically_similar!(0.0001, diff, 10.0);
+    }
+
+    #[test]
+    fn p2_quantile_estimate() {
+        let mut tracker = P2Quantile::new(0.95);
+        assert_eq!(tracker.estimate(), None);
+
+        // 1..=1000 in a scrambled but deterministic order
+        for i in 0..1000 {
+            tracker.observe(((i * 7919) % 1000 + 1) as f64);
+        }
+        assert_numerically_similar!(15.0, tracker.estimate().unwrap(), 950.0);
+    }
+
+    #[test]
+    fn continuous_authenticator_verdicts() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 1000.0, 100.0));
+        stats.insert(('b', 'a'), DigraphStats::new(10, 1000.0, 100.0));
+
+        let diff_params = KeynomeAuthenticatorDiffParams {
+            min_instances: 2,
+            ..Default::default()
+        };
+        let profile = UserProfile::new(12, 6, 100.0, &diff_params, &stats);
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+
+        let mut ts = 10000;
+        let mut verdicts = Vec::new();
+        for i in 0..6 {
+            verdicts.push(auth.add_key_event(KeyEvent::new(ts, if i % 2 == 0 { 'a' } else { 'b' })));
+            ts += 1000;
+        }
+        assert_eq!(verdicts[0], AuthVerdict::Warming);
+        assert_eq!(verdicts[5], AuthVerdict::Accept);
+
+        // a much slower typist drifts out of the threshold
+        let mut verdict = AuthVerdict::Warming;
+        for i in 0..6 {
+            verdict = auth.add_key_event(KeyEvent::new(ts, if i % 2 == 0 { 'a' } else { 'b' }));
+            ts += 3000;
+        }
+        assert_eq!(verdict, AuthVerdict::Reject(RejectReason::DiffExceeded));
+    }
+
+    #[test]
+    fn continuous_authenticator_adaptive_threshold() {
+        let mut adaptive = AdaptiveThreshold::new(0.95, 0.2, 5);
+        for diff in &[10.0, 20.0, 30.0, 40.0] {
+            adaptive.observe(*diff);
+        }
+        assert_eq!(adaptive.threshold(), None);
+
+        adaptive.observe(50.0);
+        assert_numerically_similar!(0.0001, adaptive.threshold().unwrap(), 60.0);
+    }
+
+    #[test]
+    fn user_profile_validation() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        let diff_params = KeynomeAuthenticatorDiffParams::default();
+
+        assert!(UserProfile::new(100, 10, 50.0, &diff_params, &stats).validate().is_ok());
+        assert!(UserProfile::new(100, 10, 0.0, &diff_params, &stats).validate().is_err());
+        assert!(UserProfile::new(100, 10, -1.0, &diff_params, &stats).validate().is_err());
+        assert!(UserProfile::new(10, 100, 50.0, &diff_params, &stats).validate().is_err());
+        assert!(UserProfile::new(100, 10, 50.0, &diff_params, &HashMap::new()).validate().is_err());
+
+        let mut diff_params = KeynomeAuthenticatorDiffParams { frequency_weighting: FrequencyWeighting::Table, ..Default::default() };
+        assert!(UserProfile::new(100, 10, 50.0, &diff_params, &stats).validate().is_err());
+        diff_params.frequency_table = Some(HashMap::new());
+        assert!(UserProfile::new(100, 10, 50.0, &diff_params, &stats).validate().is_err());
+        diff_params.frequency_table.as_mut().unwrap().insert(('a', 'b'), 0.01);
+        assert!(UserProfile::new(100, 10, 50.0, &diff_params, &stats).validate().is_ok());
+    }
+
+    #[test]
+    fn keystroke_logger_pressure_statistics() {
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_key_event(KeyEvent::new(10000, 'a').with_pressure(0.2));
+        kstr.add_key_event(KeyEvent::new(11000, 'b'));
+        kstr.add_key_event(KeyEvent::new(12000, 'a').with_pressure(0.4));
+        kstr.add_key_event(KeyEvent::new(13000, 'b').with_pressure(0.5));
+
+        let stats = kstr.compute_pressure_statistics();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[&'a'].size_samples, 2);
+        assert_numerically_similar!(0.0001, stats[&'a'].mean, 0.3);
+
+        // no pressure at all yields no stats rather than failing
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(11000, 'a'));
+        assert!(kstr.compute_pressure_statistics().is_empty());
+    }
+
+    #[test]
+    fn keynome_authenticator_compute_diff_with_limit() {
+        let mut stats_profile = HashMap::new();
+        let mut stats_sample = HashMap::new();
+        for k in &[('a', 'b'), ('b', 'c'), ('c', 'd')] {
+            stats_profile.insert(*k, DigraphStats::new(10, 100.0, 10.0));
+            stats_sample.insert(*k, DigraphStats::new(10, 110.0, 10.0));
+        }
+        let mut diff_params = KeynomeAuthenticatorDiffParams::default();
+
+        let diff = KeynomeAuthenticator::compute_diff_with_limit(&stats_profile, &stats_sample, &diff_params, 5.0);
+        assert_numerically_similar!(0.0001, diff, 10.0);
+
+        let diff = KeynomeAuthenticator::compute_diff_with_limit(&stats_profile, &stats_sample, &diff_params, 100.0);
+        assert_numerically_similar!(0.0001, diff, 30.0);
+
+        // negative weights make the sum non-monotone, so no early exit
+        let mut weights = HashMap::new();
+        weights.insert(('a', 'b'), -1.0);
+        diff_params.weights = Some(weights);
+        let diff = KeynomeAuthenticator::compute_diff_with_limit(&stats_profile, &stats_sample, &diff_params, 5.0);
+        assert_numerically_similar!(0.0001, diff, 10.0);
+        assert!(!diff_params.is_monotone());
+    }
+
+    #[test]
+    fn keystroke_logger_digraph_statistics_since() {
+        let mut kstr = KeystrokeLogger::new();
+
+        // older a-b digraphs, diffs = [1000, 1000]
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(11000, 'b'));
+        kstr.add_key_event(KeyEvent::new(12000, 'a'));
+        kstr.add_key_event(KeyEvent::new(13000, 'b'));
+
+        // recent a-b digraphs, diffs = [200, 400]
+        kstr.add_key_event(KeyEvent::new(20000, 'a'));
+        kstr.add_key_event(KeyEvent::new(20200, 'b'));
+        kstr.add_key_event(KeyEvent::new(20500, 'a'));
+        kstr.add_key_event(KeyEvent::new(20900, 'b'));
+
+        let stats = kstr.compute_digraph_statistics_since(1000);
+        assert_eq!(stats[&('a', 'b')].size_samples, 2);
+        assert_numerically_similar!(0.01, stats[&('a', 'b')].mean, 300.0);
+        assert!(!stats.contains_key(&('b', 'a')));
+
+        let stats = kstr.compute_digraph_statistics_since(100000);
+        assert_eq!(stats[&('a', 'b')].size_samples, 4);
+        assert!(KeystrokeLogger::new().compute_digraph_statistics_since(1000).is_empty());
+    }
+
+    #[test]
+    fn keystroke_logger_key_normalizer() {
+        let normalizer = KeystrokeLogger::us_shift_normalizer();
+        assert_eq!(normalizer[&'!'], '1');
+        assert_eq!(normalizer[&'?'], '/');
+        assert_eq!(normalizer[&'"'], '\'');
+        assert_eq!(normalizer[&'|'], '\\');
+
+        let mut kstr = KeystrokeLogger::new();
+        kstr.set_key_normalizer(normalizer);
+        kstr.add_key_event(KeyEvent::new(10000, 'A'));
+        kstr.add_key_event(KeyEvent::new(11000, '!'));
+        kstr.add_key_event(KeyEvent::new(12000, 'b'));
+
+        let keys: Vec<char> = kstr.get_key_events().iter().map(|e| e.key).collect();
+        assert_eq!(keys, vec!['a', '1', 'b']);
+
+        // a profile enrolled with shift normalization pools live keys the same way
+        let diff_params = KeynomeAuthenticatorDiffParams { normalize_shift: true, ..Default::default() };
+        let profile = UserProfile::new(12, 6, 100.0, &diff_params, &HashMap::new());
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+        auth.add_key_event(KeyEvent::new(10000, 'A'));
+        auth.add_key_event(KeyEvent::new(11000, '?'));
+        let keys: Vec<char> = auth.get_key_events().iter().map(|e| e.key).collect();
+        assert_eq!(keys, vec!['a', '/']);
+    }
+
+    #[test]
+    fn user_profile_compression() {
+        let mut stats = HashMap::new();
+        for (i, c) in "abcdefghij".chars().enumerate() {
+            stats.insert((c, 'x'), DigraphStats::new(10 + i, 100.0 * i as f64, 10.0));
+        }
+        let profile = UserProfile::new(100, 10, 50.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+
+        let compressed = profile.to_compressed_bytes();
+        assert!(compressed.len() < profile.serialize().len());
+
+        let decompressed = UserProfile::from_compressed_bytes(&compressed).unwrap();
+        assert_eq!(decompressed.n_profile, 100);
+        assert_eq!(decompressed.stats.len(), 10);
+        assert_eq!(decompressed.stats[&('c', 'x')].size_samples, 12);
+
+        assert!(UserProfile::from_compressed_bytes(b"not gzip").is_err());
+    }
+
+    #[test]
+    fn user_profile_genuine_diffs_serialization() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        let mut profile = UserProfile::new(100, 10, 20.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        assert!(profile.genuine_diff_stats().is_none());
+
+        profile.set_genuine_diffs(&[10.0, 20.0, 30.0]);
+        let deserialized = UserProfile::deserialize(&profile.serialize());
+        assert_eq!(deserialized.genuine_diffs, vec![10.0, 20.0, 30.0]);
+
+        let dist = deserialized.genuine_diff_stats().unwrap();
+        assert_numerically_similar!(0.0001, dist.mean, 20.0);
+        assert_numerically_similar!(0.0001, dist.std, 10.0);
+
+        let summary: serde_json::Value = serde_json::from_str(&deserialized.summary_json()).unwrap();
+        assert_eq!(summary["genuine_diffs"]["size_samples"], 3);
+    }
+
+    #[test]
+    fn keynome_authenticator_no_shared_digraphs() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 1000.0, 100.0));
+        stats.insert(('b', 'a'), DigraphStats::new(10, 1000.0, 100.0));
+
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('x', 'y'), DigraphStats::new(10, 1000.0, 100.0));
+        let diff_params = KeynomeAuthenticatorDiffParams::default();
+        assert_eq!(KeynomeAuthenticator::compute_diff_checked(&stats, &stats_sample, &diff_params), None);
+        assert_eq!(KeynomeAuthenticator::compute_diff_checked(&stats, &stats, &diff_params), Some(0.0));
+
+        // a stranger typing unrelated keys is not accepted
+        let profile = UserProfile::new(12, 6, 100.0, &diff_params, &stats);
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+        let mut verdict = AuthVerdict::Warming;
+        for i in 0..6 {
+            verdict = auth.add_key_event(KeyEvent::new(10000 + 1000 * i, if i % 2 == 0 { 'x' } else { 'y' }));
+        }
+        assert_eq!(verdict, AuthVerdict::Insufficient);
+
+        // with a coverage requirement the same window is a rejection with a reason
+        let diff_params = KeynomeAuthenticatorDiffParams { min_overlap_ratio: Some(0.5), ..Default::default() };
+        let profile = UserProfile::new(12, 6, 100.0, &diff_params, &stats);
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+        for i in 0..6 {
+            verdict = auth.add_key_event(KeyEvent::new(10000 + 1000 * i, if i % 2 == 0 { 'x' } else { 'y' }));
+        }
+        assert_eq!(verdict, AuthVerdict::Reject(RejectReason::InsufficientOverlap));
+    }
+
+    #[test]
+    fn seeded_rng_reproducibility() {
+        let mut rng1 = SeededRng::from_seed(Some(42));
+        let mut rng2 = SeededRng::from_seed(Some(42));
+        let seq1: Vec<u64> = (0..10).map(|_| rng1.next_u64()).collect();
+        let seq2: Vec<u64> = (0..10).map(|_| rng2.next_u64()).collect();
+        assert_eq!(seq1, seq2);
+
+        let mut rng3 = SeededRng::new(43);
+        assert_ne!(seq1[0], rng3.next_u64());
+
+        let mut rng = SeededRng::new(7);
+        for _ in 0..1000 {
+            let x = rng.next_f64();
+            assert!((0.0..1.0).contains(&x));
+            assert!(rng.next_below(5) < 5);
+        }
+
+        let mut items1: Vec<usize> = (0..20).collect();
+        let mut items2: Vec<usize> = (0..20).collect();
+        SeededRng::new(1).shuffle(&mut items1);
+        SeededRng::new(1).shuffle(&mut items2);
+        assert_eq!(items1, items2);
+        items1.sort();
+        assert_eq!(items1, (0..20).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn keystroke_logger_key_arrival_statistics() {
+        let mut kstr = KeystrokeLogger::new();
+
+        // arrivals of b: 1000 (after a), 3000 (after c); arrival of c: 2000
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(11000, 'b'));
+        kstr.add_key_event(KeyEvent::new(13000, 'c'));
+        kstr.add_key_event(KeyEvent::new(16000, 'b'));
+
+        let stats = kstr.compute_key_arrival_statistics();
+        assert_eq!(stats[&'b'].size_samples, 2);
+        assert_numerically_similar!(0.01, stats[&'b'].mean, 2000.0);
+        assert!(!stats.contains_key(&'a'));
+        assert!(!stats.contains_key(&'c'));
+    }
+
+    #[test]
+    fn continuous_authenticator_cooldown() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 1000.0, 100.0));
+        stats.insert(('b', 'a'), DigraphStats::new(10, 1000.0, 100.0));
+        let profile = UserProfile::new(12, 6, 100.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+        auth.set_cooldown(Cooldown::Keystrokes(3));
+
+        // slow typing gets rejected
+        let mut ts = 10000;
+        for i in 0..6 {
+            auth.add_key_event(KeyEvent::new(ts, if i % 2 == 0 { 'a' } else { 'b' }));
+            ts += 3000;
+        }
+
+        // back to normal speed, the window would already be accepted after 6 keystrokes
+        let mut verdicts = Vec::new();
+        for i in 0..12 {
+            verdicts.push(auth.add_key_event(KeyEvent::new(ts, if i % 2 == 0 { 'a' } else { 'b' })));
+            ts += 1000;
+        }
+        assert_eq!(verdicts[..3], [AuthVerdict::Reject(RejectReason::DiffExceeded); 3]);
+        assert_eq!(verdicts[11], AuthVerdict::Accept);
+
+        // a time-based cooldown holds the rejection for the given period
+        let mut auth = ContinuousAuthenticator::new(
+            UserProfile::new(12, 6, 100.0, &KeynomeAuthenticatorDiffParams::default(), &stats), 1.5);
+        auth.set_cooldown(Cooldown::Millis(100000));
+        let mut ts = 10000;
+        for i in 0..6 {
+            auth.add_key_event(KeyEvent::new(ts, if i % 2 == 0 { 'a' } else { 'b' }));
+            ts += 3000;
+        }
+        for i in 0..12 {
+            assert_eq!(auth.add_key_event(KeyEvent::new(ts, if i % 2 == 0 { 'a' } else { 'b' })), AuthVerdict::Reject(RejectReason::DiffExceeded));
+            ts += 1000;
+        }
+    }
+
+    #[test]
+    fn user_profile_metadata_serialization() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        let mut profile = UserProfile::new(100, 10, 20.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        assert!(UserProfile::deserialize(&profile.serialize()).metadata.is_none());
+
+        profile.metadata = Some(ProfileMetadata::new(Some("alice")));
+        let metadata = UserProfile::deserialize(&profile.serialize()).metadata.unwrap();
+        assert_eq!(metadata.user_label, Some("alice".to_string()));
+        assert_eq!(metadata.tool_version, env!("CARGO_PKG_VERSION"));
+        assert!(metadata.created_at_ms > 0);
+    }
+
+    #[test]
+    fn keystroke_logger_rescale_timestamps() {
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(10100, 'b'));
+        kstr.add_key_event(KeyEvent::new(10300, 'a'));
+        kstr.add_key_event(KeyEvent::new(10400, 'b'));
+
+        kstr.rescale_timestamps(1.5);
+        let timestamps: Vec<u128> = kstr.get_key_events().iter().map(|e| e.timestamp_ms()).collect();
+        assert_eq!(timestamps, vec![10000, 10150, 10450, 10600]);
+
+        let stats = kstr.compute_digraph_statistics();
+        assert_numerically_similar!(0.01, stats[&('a', 'b')].mean, 150.0);
+
+        // an event older than the front one, as jitter can produce
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(9990, 'b'));
+        kstr.add_key_event(KeyEvent::new(10100, 'c'));
+        kstr.rescale_timestamps(2.0);
+        let timestamps: Vec<u128> = kstr.get_key_events().iter().map(|e| e.timestamp_ms()).collect();
+        assert_eq!(timestamps, vec![10010, 9990, 10210]);
+    }
+
+    #[test]
+    fn keynome_authenticator_min_overlap_ratio() {
+        let mut stats_profile = HashMap::new();
+        for k in &[('a', 'b'), ('b', 'c'), ('c', 'd'), ('d', 'e')] {
+            stats_profile.insert(*k, DigraphStats::new(10, 100.0, 10.0));
+        }
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(5, 110.0, 10.0));
+
+        let mut diff_params = KeynomeAuthenticatorDiffParams::default();
+        assert_numerically_similar!(0.0001, KeynomeAuthenticator::overlap_ratio(&stats_profile, &stats_sample, &diff_params), 0.25);
+        assert_eq!(KeynomeAuthenticator::compute_diff_checked(&stats_profile, &stats_sample, &diff_params), Some(10.0));
+
+        diff_params.min_overlap_ratio = Some(0.5);
+        assert_eq!(KeynomeAuthenticator::compute_diff_checked(&stats_profile, &stats_sample, &diff_params), None);
+
+        stats_sample.insert(('b', 'c'), DigraphStats::new(5, 110.0, 10.0));
+        assert_eq!(KeynomeAuthenticator::compute_diff_checked(&stats_profile, &stats_sample, &diff_params), Some(20.0));
+    }
+
+    #[test]
+    fn keystroke_logger_small_sample_fast_path() {
+        let keystrokes = "the quick brown fox jumps over the lazy dog and then the dog sleeps";
+
+        let mut kstr = KeystrokeLogger::new();
+        let mut ts = 10000;
+        for (i, c) in keystrokes.chars().filter(|c| c.is_ascii_lowercase()).enumerate() {
+            kstr.add_key_event(KeyEvent::new(ts, c));
+            ts += 100 + (i as u128 * 37) % 200;
+        }
+
+        let fast = kstr.compute_letter_digraph_statistics_from(0);
+        let hashed = kstr.compute_hashed_digraph_statistics_from(0);
+        assert_eq!(fast.len(), hashed.len());
+        for (k, v) in hashed.iter() {
+            assert_eq!(fast[k].size_samples, v.size_samples);
+            assert_numerically_similar!(0.0001, fast[k].mean, v.mean);
+            assert_numerically_similar!(0.0001, fast[k].std, v.std);
+        }
+    }
+
+    #[test]
+    fn keystroke_logger_events_csv() {
+        let mut events = VecDeque::new();
+        events.push_back(KeyEvent::new(10000, 'a').with_release(10080));
+        events.push_back(KeyEvent::new(10200, ','));
+        events.push_back(KeyEvent::new(10300, '"').with_release(10350));
+        events.push_back(KeyEvent::new(10400, ' '));
+        events.push_back(KeyEvent::new(10500, '\n'));
+
+        let csv = KeystrokeLogger::serialize_events_csv(&events);
+        assert!(csv.starts_with("key,press_time,release_time\na,10000,10080\n\",\",10200,\n"));
+
+        let parsed = KeystrokeLogger::deserialize_events_csv(&csv).unwrap();
+        let keys: Vec<char> = parsed.iter().map(|e| e.key).collect();
+        assert_eq!(keys, vec!['a', ',', '"', ' ', '\n']);
+        assert_eq!(parsed[0].release_ms(), Some(10080));
+        assert_eq!(parsed[1].release_ms(), None);
+        assert_eq!(parsed[2].timestamp_ms(), 10300);
+
+        assert!(KeystrokeLogger::deserialize_events_csv("ab,100,\n").is_err());
+        assert!(KeystrokeLogger::deserialize_events_csv("a,x,\n").is_err());
+    }
+
+    #[test]
+    fn keystroke_logger_digraph_break() {
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(11000, 'b'));
+        kstr.add_key_event(KeyEvent::new(12000, 'a'));
+        kstr.add_key_event(KeyEvent::new(13000, 'b'));
+        kstr.add_key_event(KeyEvent::new(14000, 'a'));
+        assert!(kstr.compute_digraph_statistics().contains_key(&('b', 'a')));
+
+        // an ignored key between b and a breaks the digraph
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(11000, 'b'));
+        kstr.add_digraph_break();
+        kstr.add_key_event(KeyEvent::new(12000, 'a'));
+        kstr.add_key_event(KeyEvent::new(13000, 'b'));
+        kstr.add_digraph_break();
+        kstr.add_key_event(KeyEvent::new(14000, 'a'));
+
+        let stats = kstr.compute_digraph_statistics();
+        assert_eq!(stats[&('a', 'b')].size_samples, 2);
+        assert!(!stats.contains_key(&('b', 'a')));
+        assert!(!kstr.compute_key_arrival_statistics().contains_key(&'a'));
+
+        let profile = UserProfile::new(12, 6, 100.0, &KeynomeAuthenticatorDiffParams::default(), &HashMap::new());
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+        auth.add_key_event(KeyEvent::new(10000, 'a'));
+        auth.add_digraph_break();
+        auth.add_key_event(KeyEvent::new(11000, 'b'));
+        assert!(auth.get_key_events()[1].follows_break);
+    }
+
+    #[test]
+    fn continuous_authenticator_is_ready() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 1000.0, 100.0));
+        stats.insert(('b', 'a'), DigraphStats::new(10, 1000.0, 100.0));
+        let profile = UserProfile::new(12, 6, 100.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+        assert!(!auth.is_ready());
+
+        // a full window of unrelated keys is still not enough
+        for i in 0..6 {
+            auth.add_key_event(KeyEvent::new(10000 + 1000 * i, if i % 2 == 0 { 'x' } else { 'y' }));
+        }
+        assert!(!auth.is_ready());
+
+        for i in 0..6 {
+            auth.add_key_event(KeyEvent::new(20000 + 1000 * i, if i % 2 == 0 { 'a' } else { 'b' }));
+        }
+        assert!(auth.is_ready());
+    }
+
+    #[test]
+    fn population_normalizer() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('b', 'c'), DigraphStats::new(10, 200.0, 10.0));
+        let profile = UserProfile::new(100, 10, 20.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+
+        let mut stats_population = HashMap::new();
+        stats_population.insert(('a', 'b'), DigraphStats::new(1000, 150.0, 30.0));
+        stats_population.insert(('b', 'c'), DigraphStats::new(1000, 250.0, 30.0));
+
+        let normalizer = PopulationNormalizer::from_population(&profile, &stats_population).unwrap();
+        assert_numerically_similar!(0.0001, normalizer.population_diff, 100.0);
+        assert_numerically_similar!(0.0001, normalizer.normalize(25.0), 0.25);
+
+        assert!(PopulationNormalizer::from_population(&profile, &HashMap::new()).is_none());
+        assert!(PopulationNormalizer::from_population(&profile, &stats).is_none());
+    }
+
+    #[test]
+    fn user_profile_update_with_sample() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        let mut profile = UserProfile::new(100, 10, 20.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(4, 200.0, 10.0));
+        stats_sample.insert(('c', 'd'), DigraphStats::new(4, 300.0, 10.0));
+        profile.update_with_sample(&stats_sample, 0.25);
+
+        // mean 100 + 0.25 * 100, var = 0.75 * 100 + 0.25 * 100 + 0.1875 * 10000
+        assert_numerically_similar!(0.0001, profile.stats[&('a', 'b')].mean, 125.0);
+        assert_numerically_similar!(0.0001, profile.stats[&('a', 'b')].std, 1975.0_f64.sqrt());
+        assert_eq!(profile.stats[&('a', 'b')].size_samples, 14);
+        assert_numerically_similar!(0.0001, profile.stats[&('c', 'd')].mean, 300.0);
+    }
+
+    #[test]
+    fn keystroke_logger_digraph_statistics_nested_serialization() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(3, 100.0, 10.0));
+
+        let serialized = KeystrokeLogger::serialize_digraph_statistics(&stats);
+        let value: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(value["a-b"]["size_samples"], 3);
+
+        let legacy = r#"{"a-b": "{\"size_samples\":3,\"mean\":100.0,\"std\":10.0}"}"#;
+        let deserialized = KeystrokeLogger::deserialize_digraph_statistics(legacy);
+        assert_eq!(deserialized[&('a', 'b')].size_samples, 3);
+        assert_numerically_similar!(0.0001, deserialized[&('a', 'b')].mean, 100.0);
+    }
+
+    #[test]
+    fn keystroke_logger_timing_features() {
+        let mut kstr = KeystrokeLogger::new();
+
+        // a-b pairs: (press a, release a) -> (press b, release b)
+        kstr.add_key_event(KeyEvent::new(10000, 'a').with_release(10100));
+        kstr.add_key_event(KeyEvent::new(10300, 'b').with_release(10350));
+        kstr.add_key_event(KeyEvent::new(11000, 'a').with_release(11200));
+        kstr.add_key_event(KeyEvent::new(11150, 'b').with_release(11250));
+
+        let stats = kstr.compute_all_timing_statistics();
+        // DD = [300, 150], DU = [350, 250], UD = [200, -50], UU = [250, 50]
+        assert_numerically_similar!(0.01, stats[&TimingFeature::DownDown][&('a', 'b')].mean, 225.0);
+        assert_numerically_similar!(0.01, stats[&TimingFeature::DownUp][&('a', 'b')].mean, 300.0);
+        assert_numerically_similar!(0.01, stats[&TimingFeature::UpDown][&('a', 'b')].mean, 75.0);
+        assert_numerically_similar!(0.01, stats[&TimingFeature::UpUp][&('a', 'b')].mean, 150.0);
+
+        // features involving releases are unavailable without release times
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(10300, 'b'));
+        kstr.add_key_event(KeyEvent::new(11000, 'a'));
+        kstr.add_key_event(KeyEvent::new(11150, 'b'));
+        assert!(kstr.compute_timing_statistics(TimingFeature::UpUp).is_empty());
+        assert_eq!(kstr.compute_timing_statistics(TimingFeature::DownDown).len(), 1);
+    }
+
+    #[test]
+    fn window_weighting_weighted_mean() {
+        let diffs = [4.0, 2.0, 1.0];
+        assert_numerically_similar!(0.0001, WindowWeighting::Uniform.weighted_mean(&diffs), 7.0 / 3.0);
+        // (4*1 + 2*2 + 1*3) / 6
+        assert_numerically_similar!(0.0001, WindowWeighting::Linear.weighted_mean(&diffs), 11.0 / 6.0);
+    }
+
+    #[test]
+    fn keynome_authenticator_compute_diff_top_n() {
+        let stats = |n_ab: usize, n_bc: usize, mean_bc: f64| {
+            let mut stats = HashMap::new();
+            stats.insert(('a', 'b'), DigraphStats::new(n_ab, 100.0, 1.0));
+            stats.insert(('b', 'c'), DigraphStats::new(n_bc, mean_bc, 1.0));
+            stats
+        };
+        let stats_profile = stats(50, 3, 100.0);
+        let stats_sample = stats(10, 10, 300.0);
+
+        let diff_params = KeynomeAuthenticatorDiffParams::default();
+        assert_numerically_similar!(0.0001, KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params), 200.0);
+
+        // the poorly estimated b-c is left out
+        let diff_params = KeynomeAuthenticatorDiffParams { top_n: Some(1), ..Default::default() };
+        assert_numerically_similar!(0.0001, KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params), 0.0);
+    }
+
+    #[test]
+    fn keynome_authenticator_compute_diff_normalize_speed() {
+        let stats = |scale: f64| {
+            let mut stats = HashMap::new();
+            stats.insert(('a', 'b'), DigraphStats::new(10, 100.0 * scale, 1.0));
+            stats.insert(('b', 'c'), DigraphStats::new(10, 200.0 * scale, 1.0));
+            stats
+        };
+        let stats_profile = stats(1.0);
+        let stats_sample = stats(1.5);
+
+        let diff_params = KeynomeAuthenticatorDiffParams::default();
+        assert_numerically_similar!(0.0001, KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params), 150.0);
+
+        // same rhythm, only slower
+        let diff_params = KeynomeAuthenticatorDiffParams { normalize_speed: true, ..Default::default() };
+        assert_numerically_similar!(0.0001, KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params), 0.0);
+    }
+
+    #[test]
+    fn continuous_authenticator_decision_boundary() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 1.0));
+        let mut profile = UserProfile::new(12, 6, 10.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        profile.set_genuine_diffs(&[8.0, 12.0]);
+        let auth = ContinuousAuthenticator::new(profile, 1.5);
+
+        let boundary = auth.decision_boundary(5);
+        assert_numerically_similar!(0.0001, boundary.threshold, 15.0);
+        assert_numerically_similar!(0.0001, boundary.genuine_mean.unwrap(), 10.0);
+        let accepted: Vec<bool> = boundary.sweep.iter().map(|(_, a)| *a).collect();
+        assert_eq!(accepted, vec![true, true, true, false, false]);
+        assert_numerically_similar!(0.0001, boundary.sweep[4].0, 30.0);
+    }
+
+    #[test]
+    fn keystroke_logger_rollover_rate() {
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(10100, 'b'));
+        assert_eq!(kstr.compute_rollover_rate(), None);
+
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_key_event(KeyEvent::new(10000, 'a').with_release(10150));
+        kstr.add_key_event(KeyEvent::new(10100, 'b').with_release(10200));
+        kstr.add_key_event(KeyEvent::new(10300, 'c').with_release(10400));
+        // pressed out of order, before 'c' was even pressed
+        kstr.add_key_event(KeyEvent::new(10250, 'd').with_release(10350));
+        kstr.add_key_event(KeyEvent::new(10500, 'e').with_release(10550));
+        assert_numerically_similar!(0.0001, kstr.compute_rollover_rate().unwrap(), 0.5);
+
+        // the out-of-order pair must not underflow
+        let stats = kstr.compute_digraph_statistics();
+        assert!(stats.is_empty());
+    }
+
+    #[test]
+    fn keynome_authenticator_compute_diff_tolerance() {
+        let mut stats_profile = HashMap::new();
+        stats_profile.insert(('a', 'b'), DigraphStats::new(10, 100.0, 1.0));
+        stats_profile.insert(('b', 'c'), DigraphStats::new(10, 200.0, 1.0));
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(10, 104.0, 1.0));
+        stats_sample.insert(('b', 'c'), DigraphStats::new(10, 250.0, 1.0));
+
+        let diff_params = KeynomeAuthenticatorDiffParams { dispersion: true, tolerance_ms: 5.0, ..Default::default() };
+        let (diff, n_comparisons) = KeynomeAuthenticator::compute_diff_counted(&stats_profile, &stats_sample, &diff_params, f64::INFINITY);
+        // only b-c contributes, a-b is within the band
+        assert_numerically_similar!(0.01, diff, 50.0 / 1.001);
+        assert_eq!(n_comparisons, 2);
+    }
+
+    #[test]
+    fn evaluation_report_compute() {
+        assert!(EvaluationReport::compute(&[1.0], &[], 1.0).is_none());
+
+        let genuine = [1.0, 2.0, 3.0, 6.0];
+        let impostor = [4.0, 5.0, 7.0, 8.0];
+        let report = EvaluationReport::compute(&genuine, &impostor, 4.5).unwrap();
+        assert_numerically_similar!(0.0001, report.far, 0.25);
+        assert_numerically_similar!(0.0001, report.frr, 0.25);
+        assert_numerically_similar!(0.0001, report.eer, 0.25);
+    }
+
+    #[test]
+    fn user_profile_window_diffs() {
+        let mut events = VecDeque::new();
+        for i in 0..25 {
+            events.push_back(KeyEvent::new(10000 + 100 * i, ['a', 'b', 'c'][i as usize % 3]));
+        }
+        let mut kstr = KeystrokeLogger::new();
+        for ev in events.iter() {
+            kstr.add_key_event(ev.clone());
+        }
+        let profile = UserProfile::new(24, 6, 1.0, &KeynomeAuthenticatorDiffParams::default(),
+                                       &kstr.compute_digraph_statistics());
+
+        // the trailing partial window is dropped
+        let diffs = profile.window_diffs(&events);
+        assert_eq!(diffs.len(), 4);
+        assert!(diffs.iter().all(|d| *d == 0.0));
+    }
+
+    #[test]
+    fn keystroke_logger_summary_only() {
+        let keys = ['a', 'b', 'c', 'a', 'b', 'a', 'b', 'c', 'a', 'c'];
+        let mut kstr = KeystrokeLogger::new();
+        let mut kstr_summary = KeystrokeLogger::new();
+        for (i, key) in keys.iter().enumerate() {
+            let ev = KeyEvent::new(10000 + (i * i * 7 + 90) as u128 * i as u128, *key);
+            kstr.add_key_event(ev.clone());
+            kstr_summary.add_key_event(ev);
+            if i == 3 {
+                kstr_summary.set_summary_only();
+            }
+        }
+
+        assert_eq!(kstr_summary.get_key_events().len(), 2);
+        let stats = kstr.compute_digraph_statistics();
+        let stats_summary = kstr_summary.compute_digraph_statistics();
+        assert_eq!(stats.len(), stats_summary.len());
+        for (k, v) in stats.iter() {
+            assert_eq!(v.size_samples, stats_summary[k].size_samples);
+            assert_numerically_similar!(0.0001, v.mean, stats_summary[k].mean);
+            assert_numerically_similar!(0.0001, v.std, stats_summary[k].std);
+        }
+    }
+
+    #[test]
+    fn nearest_digraph_shares_a_key() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 1.0));
+        stats.insert(('a', 'c'), DigraphStats::new(10, 160.0, 1.0));
+        stats.insert(('d', 'x'), DigraphStats::new(10, 150.0, 1.0));
+        stats.insert(('x', 'y'), DigraphStats::new(10, 500.0, 1.0));
+
+        // a-b, a-c and d-x share a key with a-x, averaging to 136.7
+        assert_eq!(nearest_digraph(&stats, ('a', 'x')), Some((('d', 'x'), 150.0)));
+        assert_eq!(nearest_digraph(&stats, ('a', 'b')), Some((('a', 'b'), 100.0)));
+        assert_eq!(nearest_digraph(&stats, ('q', 'r')), None);
+    }
+
+    #[test]
+    fn user_profile_deserialize_v1() {
+        // mean/std only, diff params without later fields, plus a key from a newer version
+        let stats = r#"{"a-b":"{\"size_samples\":10,\"mean\":100.0,\"std\":10.0}"}"#;
+        let diff_params = r#"{"dispersion":false,"min_instances":2,"max_comparisons":100,"from_the_future":1}"#;
+        let mut map = HashMap::new();
+        map.insert("n_profile", "12".to_string());
+        map.insert("n_sample", "6".to_string());
+        map.insert("diff_base", "1.5".to_string());
+        map.insert("diff_params", diff_params.to_string());
+        map.insert("stats", stats.to_string());
+        map.insert("from_the_future", "1".to_string());
+
+        let profile = UserProfile::deserialize(&serde_json::to_string(&map).unwrap());
+        let v = &profile.stats[&('a', 'b')];
+        assert_numerically_similar!(0.0001, v.mean, 100.0);
+        assert!(!v.has_quantiles());
+        assert!(v.median.is_nan());
+        assert_eq!(profile.diff_params.top_n, None);
+
+        // quantiles stay absent through a round trip
+        let profile = UserProfile::deserialize(&profile.serialize());
+        assert!(!profile.stats[&('a', 'b')].has_quantiles());
+    }
+
+    #[test]
+    fn digraph_stats_required_samples() {
+        let v = DigraphStats::new(5, 100.0, 20.0);
+        // (2 * 1.96 * 20 / 10)^2 = 61.47
+        assert_eq!(v.required_samples(10.0, 1.96), 62);
+        assert_eq!(v.required_samples(1000.0, 1.96), 2);
+        assert_eq!(v.required_samples(0.0, 1.96), usize::MAX);
+    }
+
+    #[test]
+    fn keynome_authenticator_compute_diff_zscore() {
+        let mut stats_profile = HashMap::new();
+        stats_profile.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats_profile.insert(('b', 'c'), DigraphStats::new(10, 200.0, 0.0));
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(10, 120.0, 10.0));
+        stats_sample.insert(('b', 'c'), DigraphStats::new(10, 203.0, 10.0));
+
+        // 2 stds off on a-b, and b-c's zero std is floored to 1ms
+        let diff_params = KeynomeAuthenticatorDiffParams { metric: DistanceMetric::ZScore, ..Default::default() };
+        assert_numerically_similar!(0.0001, KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params), 5.0);
+    }
+
+    #[test]
+    fn apply_noise_models() {
+        let mut events = VecDeque::new();
+        for i in 0..100 {
+            events.push_back(KeyEvent::new(10000 + 137 * i, 'a').with_release(10050 + 137 * i));
+        }
+
+        let noisy = apply_noise(&events, &mut Granularity { step_ms: 16 });
+        assert!(noisy.iter().all(|ev| ev.timestamp_ms() % 16 == 0 && ev.release_ms().unwrap() % 16 == 0));
+
+        let noisy = apply_noise(&events, &mut Jitter { std_ms: 20.0, rng: SeededRng::new(7) });
+        let offsets: Vec<f64> = events.iter().zip(noisy.iter())
+            .map(|(ev, nv)| nv.timestamp_ms() as f64 - ev.timestamp_ms() as f64)
+            .collect();
+        let std = standard_deviation(&offsets, mean(&offsets));
+        assert!(std > 10.0 && std < 30.0);
+
+        let noisy = apply_noise(&events, &mut RandomDelay { max_ms: 5, rng: SeededRng::new(7) });
+        assert!(events.iter().zip(noisy.iter()).all(|(ev, nv)| nv.timestamp_ms() - ev.timestamp_ms() <= 5));
+    }
+
+    #[test]
+    fn extract_windows_within_last_events() {
+        let mut events = VecDeque::new();
+        for i in 0..10 {
+            events.push_back(KeyEvent::new(i, 'a'));
+        }
+        let starts = |windows: Vec<VecDeque<KeyEvent>>| -> Vec<u128> {
+            windows.iter().map(|w| w[0].timestamp_ms()).collect()
+        };
+
+        // last 8 events: 2..10
+        assert_eq!(starts(extract_windows(&events, 8, 4, 4)), vec![2, 6]);
+        assert_eq!(starts(extract_windows(&events, 8, 4, 3)), vec![2, 5]);
+        assert_eq!(starts(extract_windows(&events, 10, 3, 3)), vec![0, 3, 6]);
+        assert!(extract_windows(&events, 8, 4, 4).iter().all(|w| w.len() == 4));
+        assert!(extract_windows(&events, 11, 4, 4).is_empty());
+        assert!(extract_windows(&events, 8, 4, 0).is_empty());
+    }
+
+    #[test]
+    fn user_profile_truncate_digraphs() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('b', 'c'), DigraphStats::new(3, 100.0, 10.0));
+        stats.insert(('c', 'd'), DigraphStats::new(7, 100.0, 10.0));
+        stats.insert(('d', 'e'), DigraphStats::new(7, 100.0, 10.0));
+        let mut profile = UserProfile::new(12, 6, 1.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+
+        profile.truncate_digraphs(2);
+        let mut kept: Vec<Digraph> = profile.stats.keys().cloned().collect();
+        kept.sort();
+        assert_eq!(kept, vec![('a', 'b'), ('c', 'd')]);
+    }
+
+    #[test]
+    fn continuous_authenticator_score_buffered_partial() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('b', 'a'), DigraphStats::new(10, 100.0, 10.0));
+        let profile = UserProfile::new(100, 50, 10.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+
+        assert_eq!(auth.score_buffered(), (AuthVerdict::Insufficient, true));
+        assert_eq!(auth.current_diff(), None);
+        for i in 0..6 {
+            let verdict = auth.add_key_event(KeyEvent::new(10000 + 100 * i, ['a', 'b'][i as usize % 2]));
+            assert_eq!(verdict, AuthVerdict::Warming);
+        }
+        assert_eq!(auth.score_buffered(), (AuthVerdict::Accept, true));
+        assert_eq!(auth.current_diff(), Some(0.0));
+    }
+
+    #[test]
+    fn continuous_authenticator_forensic_record_redaction() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        let profile = UserProfile::new(12, 6, 10.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+        for i in 0..6 {
+            auth.add_key_event(KeyEvent::new(10000 + 300 * i, ['a', 'b'][i as usize % 2]));
+        }
+
+        let record: serde_json::Value = serde_json::from_str(&auth.forensic_record(false)).unwrap();
+        assert_eq!(record["keys"], "ababab");
+        assert_numerically_similar!(0.0001, record["stats"]["a-b"]["mean"].as_f64().unwrap(), 300.0);
+        assert_numerically_similar!(0.0001, record["diff"].as_f64().unwrap(), 200.0);
+
+        let record: serde_json::Value = serde_json::from_str(&auth.forensic_record(true)).unwrap();
+        assert!(record.get("keys").is_none());
+        assert_eq!(record["stats"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn cross_validation_report_compute() {
+        let mut events = VecDeque::new();
+        let mut rng = SeededRng::new(3);
+        let mut ts = 10000;
+        for i in 0..120 {
+            ts += 100 + 50 * (i % 3) + rng.next_below(20) as u128;
+            events.push_back(KeyEvent::new(ts, ['a', 'b', 'c'][i as usize % 3]));
+        }
+        let diff_params = KeynomeAuthenticatorDiffParams::default();
+
+        let report = CrossValidationReport::compute(&events, 4, 10, &diff_params).unwrap();
+        assert_eq!(report.diffs.len(), 12);
+        // the rhythm is steady, only the small random jitter remains
+        assert!(report.mean < 30.0);
+        assert!(report.variance >= 0.0);
+
+        assert!(CrossValidationReport::compute(&events, 1, 10, &diff_params).is_none());
+    }
+
+    #[test]
+    fn feature_ensemble_score() {
+        let session = |hold_ms: u128| {
+            let mut kstr = KeystrokeLogger::new();
+            for i in 0..30 {
+                let ts = 10000 + 200 * i + 20 * (i % 3);
+                kstr.add_key_event(KeyEvent::new(ts, ['a', 'b', 'c'][i as usize % 3]).with_release(ts + hold_ms + i % 2));
+            }
+            kstr
+        };
+        let enrollment = session(80);
+        let profile = UserProfile::new(30, 10, 10.0, &KeynomeAuthenticatorDiffParams::default(),
+                                       &enrollment.compute_digraph_statistics());
+        let mut ensemble = FeatureEnsemble::from_enrollment(&enrollment);
+
+        // same flight times, but keys held 40ms longer
+        let sample = session(120);
+        let score = ensemble.score(&profile, &sample).unwrap();
+        assert!(score > 0.0);
+
+        ensemble.dwell_weight = 0.0;
+        assert_numerically_similar!(0.0001, ensemble.score(&profile, &sample).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn keynome_authenticator_compute_diff_stale_digraphs() {
+        let mut stats_profile = HashMap::new();
+        stats_profile.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats_profile.insert(('b', 'c'), DigraphStats::new(10, 100.0, 10.0));
+        stamp_last_updated(&mut stats_profile, 1000);
+        let mut profile = UserProfile::new(12, 6, 1.0, &KeynomeAuthenticatorDiffParams::default(), &stats_profile);
+
+        // only a-b is observed again, much later
+        let mut stats_update = HashMap::new();
+        stats_update.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stamp_last_updated(&mut stats_update, 100_000);
+        profile.update_with_sample(&stats_update, 0.1);
+        assert_eq!(profile.stats[&('a', 'b')].last_updated_ms, Some(100_000));
+
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(10, 110.0, 10.0));
+        stats_sample.insert(('b', 'c'), DigraphStats::new(10, 110.0, 10.0));
+        let diff_params = KeynomeAuthenticatorDiffParams { stale_after_ms: Some(60_000), stale_weight: 0.5, ..Default::default() };
+        assert_numerically_similar!(0.0001, KeynomeAuthenticator::compute_diff(&profile.stats, &stats_sample, &diff_params), 15.0);
+    }
+
+    #[test]
+    fn window_size_tradeoff_sweep() {
+        let mut events = VecDeque::new();
+        let mut rng = SeededRng::new(5);
+        let mut ts = 10000;
+        for i in 0..200 {
+            ts += [80, 250, 140, 400][i % 4] + rng.next_below(20) as u128;
+            events.push_back(KeyEvent::new(ts, ['a', 'b', 'c', 'd'][i % 4]));
+        }
+
+        let tradeoffs = WindowSizeTradeoff::sweep(
+            &events, &[4, 20, 50, 150], &KeynomeAuthenticatorDiffParams::default(), &mut SeededRng::new(1));
+        // a single 150-event window can't show stability
+        let sizes: Vec<usize> = tradeoffs.iter().map(|t| t.n_sample).collect();
+        assert_eq!(sizes, vec![20, 50]);
+        assert!(tradeoffs[0].latency_ms < tradeoffs[1].latency_ms);
+        assert!(tradeoffs.iter().all(|t| t.impostor_mean > t.genuine_mean));
+    }
+
+    #[test]
+    fn keynome_authenticator_compute_diff_base_skip_windows() {
+        let mut events = VecDeque::new();
+        let mut ts = 10000;
+        for i in 0..24 {
+            // the first window is typed twice as slowly
+            ts += if i < 6 { 200 } else { 100 } + 10 * (i % 2);
+            events.push_back(KeyEvent::new(ts, ['a', 'b'][i as usize % 2]));
+        }
+
+        let diff_params = KeynomeAuthenticatorDiffParams::default();
+        let diffs = KeynomeAuthenticator::compute_diff_base_detailed(&events, 24, 6, &diff_params).unwrap();
+        assert_eq!(diffs.len(), 4);
+
+        let diff_params = KeynomeAuthenticatorDiffParams { skip_windows: 1, ..Default::default() };
+        let diffs_skipped = KeynomeAuthenticator::compute_diff_base_detailed(&events, 24, 6, &diff_params).unwrap();
+        assert_eq!(diffs_skipped, diffs[1..].to_vec());
+
+        let diff_params = KeynomeAuthenticatorDiffParams { skip_windows: 4, ..Default::default() };
+        assert!(KeynomeAuthenticator::compute_diff_base(&events, 24, 6, &diff_params).is_none());
+    }
+
+    #[test]
+    fn keynome_authenticator_diff_events() {
+        let session = |interval: u128| -> VecDeque<KeyEvent> {
+            (0..12).map(|i| KeyEvent::new(10000 + interval * i + 5 * (i % 2), ['a', 'b'][i as usize % 2])).collect()
+        };
+        let diff_params = KeynomeAuthenticatorDiffParams::default();
+        assert_numerically_similar!(0.0001, KeynomeAuthenticator::diff_events(&session(100), &session(100), &diff_params), 0.0);
+        // a-b and b-a both 20ms slower
+        assert_numerically_similar!(0.0001, KeynomeAuthenticator::diff_events(&session(100), &session(120), &diff_params), 40.0);
+    }
+
+    #[test]
+    fn keystroke_logger_dwell_band() {
+        let mut kstr = KeystrokeLogger::new();
+        for (i, dwell) in [80, 100, 0, 5000, 90].iter().enumerate() {
+            let ts = 10000 + 10000 * i as u128;
+            kstr.add_key_event(KeyEvent::new(ts, 'a').with_release(ts + dwell));
+        }
+        assert_eq!(kstr.compute_dwell_statistics()[&'a'].size_samples, 5);
+
+        kstr.set_dwell_band(1.0, 2000.0);
+        let stats = kstr.compute_dwell_statistics();
+        assert_eq!(stats[&'a'].size_samples, 3);
+        assert_numerically_similar!(0.0001, stats[&'a'].mean, 90.0);
+    }
+
+    #[test]
+    fn keystroke_logger_key_release() {
+        let clock = mock_clock(10000);
+        let mut kstr = KeystrokeLogger::with_clock(Box::new(clock.clone()));
+
+        // a held over the press of b
+        kstr.add_keystroke('a');
+        clock.advance(60);
+        kstr.add_keystroke('b');
+        clock.advance(40);
+        assert!(kstr.add_key_release('a'));
+        clock.advance(30);
+        assert!(kstr.add_key_release('b'));
+        assert!(!kstr.add_key_release('b'));
+        assert!(!kstr.add_key_release('c'));
+        clock.advance(200);
+        kstr.add_keystroke('a');
+
+        assert_eq!(kstr.get_key_events()[2].release_ms(), None);
+        clock.advance(50);
+        assert!(kstr.add_key_release('a'));
+
+        let events = kstr.get_key_events();
+        assert_eq!(events[0].release_ms(), Some(10100));
+        assert_eq!(events[1].release_ms(), Some(10130));
+        assert_eq!(events[2].release_ms(), Some(10380));
+
+        let dwell = kstr.compute_dwell_statistics();
+        assert_eq!(dwell[&'a'].size_samples, 2);
+        assert_numerically_similar!(0.0001, dwell[&'a'].mean, 75.0);
+        assert_eq!(kstr.compute_rollover_rate(), Some(0.5));
+    }
+
+    #[test]
+    fn user_profile_overfit_score() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(2, 100.0, 10.0));
+        stats.insert(('b', 'c'), DigraphStats::new(2, 100.0, 10.0));
+        stats.insert(('c', 'd'), DigraphStats::new(40, 100.0, 10.0));
+        stats.insert(('d', 'e'), DigraphStats::new(1, 100.0, 10.0));
+        let profile = UserProfile::new(12, 6, 1.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        assert_numerically_similar!(0.0001, profile.overfit_score(), 2.0 / 3.0);
+
+        let profile = UserProfile::new(12, 6, 1.0, &KeynomeAuthenticatorDiffParams::default(), &HashMap::new());
+        assert_numerically_similar!(0.0001, profile.overfit_score(), 1.0);
+    }
+
+    #[test]
+    fn template_profile_nearest_template() {
+        // two typing modes, relaxed and hurried
+        let mut events = VecDeque::new();
+        let mut ts = 10000;
+        for i in 0..24 {
+            ts += if i < 12 { 200 } else { 100 } + 10 * (i % 2);
+            events.push_back(KeyEvent::new(ts, ['a', 'b'][i as usize % 2]));
+        }
+        let diff_params = KeynomeAuthenticatorDiffParams::default();
+        let templates = TemplateProfile::from_events(&events, 6, &diff_params);
+        assert_eq!(templates.templates.len(), 4);
+
+        let sample: VecDeque<KeyEvent> = events.range(18..24).cloned().collect();
+        let mut kstr = KeystrokeLogger::new();
+        for ev in sample {
+            kstr.add_key_event(ev);
+        }
+        let stats_sample = kstr.compute_digraph_statistics();
+        assert_numerically_similar!(0.0001, templates.compute_diff(&stats_sample).unwrap(), 0.0);
+
+        // the averaged profile sits between the modes
+        let mut kstr = KeystrokeLogger::new();
+        for ev in events {
+            kstr.add_key_event(ev);
+        }
+        assert!(KeynomeAuthenticator::compute_diff(&kstr.compute_digraph_statistics(), &stats_sample, &diff_params) > 50.0);
+    }
+
+    #[test]
+    fn keystroke_logger_trigraph_statistics() {
+        let mut kstr = KeystrokeLogger::new();
+        kstr.add_key_event(KeyEvent::new(10000, 'a'));
+        kstr.add_key_event(KeyEvent::new(11000, 'b'));
+        assert!(kstr.compute_trigraph_statistics().is_empty());
+
+        // a-b-c trigraphs, spans = [2000, 4000, 3000], mean = 3000.0, std = 1000.0
+        // b-c-a trigraphs, spans = [2000, 2000], mean = 2000.0, std = 0.0
+        // c-a-b trigraphs, spans = [4000, 2000], mean = 3000.0, std = 1414.21
+        kstr.add_key_event(KeyEvent::new(12000, 'c'));
+
+        kstr.add_key_event(KeyEvent::new(13000, 'a'));
+        kstr.add_key_event(KeyEvent::new(16000, 'b'));
+        kstr.add_key_event(KeyEvent::new(17000, 'c'));
+
+        kstr.add_key_event(KeyEvent::new(18000, 'a'));
+        kstr.add_key_event(KeyEvent::new(19000, 'b'));
+        kstr.add_key_event(KeyEvent::new(21000, 'c'));
+
+        let stats = kstr.compute_trigraph_statistics();
+        assert_eq!(stats.len(), 3);
+
+        assert_numerically_similar!(0.01, stats[&('a', 'b', 'c')].mean, 3000.0);
+        assert_numerically_similar!(0.01, stats[&('a', 'b', 'c')].std, 1000.0);
+
+        assert_numerically_similar!(0.01, stats[&('b', 'c', 'a')].mean, 2000.0);
+        assert_numerically_similar!(0.01, stats[&('b', 'c', 'a')].std, 0.0);
+
+        assert_numerically_similar!(0.01, stats[&('c', 'a', 'b')].mean, 3000.0);
+        assert_numerically_similar!(0.01, stats[&('c', 'a', 'b')].std, 1414.21);
+    }
+
+    #[test]
+    fn keystroke_logger_ngraph_statistics() {
+        let mut kstr = KeystrokeLogger::new();
+        let mut ts = 10000;
+        for (i, key) in "the quick brown fox jumps over the lazy dog".chars().enumerate() {
+            ts += 80 + (i as u128 * 37) % 90;
+            kstr.add_key_event(KeyEvent::new(ts, key));
+        }
+        assert!(kstr.compute_ngraph_statistics(1).is_empty());
+
+        let check_digraphs = |kstr: &KeystrokeLogger| {
+            let digraphs = kstr.compute_digraph_statistics();
+            let ngraphs = kstr.compute_ngraph_statistics(2);
+            assert_eq!(digraphs.len(), ngraphs.len());
+            for (k, v) in digraphs.iter() {
+                let w = &ngraphs[&vec![k.0, k.1]];
+                assert_eq!(v.size_samples, w.size_samples);
+                assert_numerically_similar!(0.0001, v.mean, w.mean);
+                assert_numerically_similar!(0.0001, v.std, w.std);
+            }
+        };
+        // spaces take the hashed path, letters only the dense one
+        check_digraphs(&kstr);
+        let mut letters = KeystrokeLogger::new();
+        for ev in kstr.get_key_events().iter().filter(|ev| ev.key != ' ') {
+            letters.add_key_event(ev.clone());
+        }
+        check_digraphs(&letters);
+
+        let trigraphs = kstr.compute_trigraph_statistics();
+        let ngraphs = kstr.compute_ngraph_statistics(3);
+        assert_eq!(trigraphs.len(), ngraphs.len());
+        let the = &ngraphs[&vec!['t', 'h', 'e']];
+        assert_eq!(the.size_samples, 2);
+        assert_numerically_similar!(0.0001, the.mean, trigraphs[&('t', 'h', 'e')].mean);
+
+        // "the " is the only 4-graph seen twice
+        let fourgraphs = kstr.compute_ngraph_statistics(4);
+        assert_eq!(fourgraphs.len(), 1);
+        assert!(fourgraphs.contains_key(&"the ".chars().collect::<Vec<char>>()));
+    }
+
+    #[test]
+    fn keystroke_logger_trigraph_statistics_allowlist() {
+        let mut kstr = KeystrokeLogger::new();
+        let mut ts = 10000;
+        for (i, key) in "thexthextheythe".chars().enumerate() {
+            ts += 100 + 10 * i as u128;
+            kstr.add_key_event(KeyEvent::new(ts, key));
+        }
+
+        let stats = kstr.compute_trigraph_statistics_in(&trigraph_allowlist(&COMMON_TRIGRAPHS));
+        assert_eq!(stats.len(), 1);
+        let v = &stats[&('t', 'h', 'e')];
+        assert_eq!(v.size_samples, 4);
+        // spans of (i+1, i+2) for the 't' at i = 0, 4, 8, 12
+        assert_numerically_similar!(0.0001, v.mean, 200.0 + 10.0 * (3.0 + 11.0 + 19.0 + 27.0) / 4.0);
+
+        assert!(trigraph_allowlist(&["th", "then"]).is_empty());
+    }
+
+    #[test]
+    fn digraph_stats_distance() {
+        let v = DigraphStats::new(10, 100.0, 10.0);
+        let vs = DigraphStats::new(10, 130.0, 20.0);
+        assert_numerically_similar!(0.0001, v.distance(&vs, DistanceMetric::Absolute), 30.0);
+        assert_numerically_similar!(0.0001, v.distance(&vs, DistanceMetric::Scaled), 30.0 / 10.001);
+        assert_numerically_similar!(0.0001, v.distance(&vs, DistanceMetric::ZScore), 3.0);
+    }
+
+    #[test]
+    fn keystroke_logger_sub_millisecond_intervals() {
+        let mut kstr = KeystrokeLogger::new();
+        // a-b 400us, b-a 1600us apart, which would round to 0ms and 2ms
+        let mut ts = 10_000_000;
+        for i in 0..6 {
+            kstr.add_key_event(KeyEvent::from_micros(ts, ['a', 'b'][i % 2]));
+            ts += [400, 1600][i % 2];
+        }
+        let stats = kstr.compute_digraph_statistics();
+        assert_numerically_similar!(0.0001, stats[&('a', 'b')].mean, 0.4);
+        assert_numerically_similar!(0.0001, stats[&('b', 'a')].mean, 1.6);
+
+        let csv = KeystrokeLogger::serialize_events_csv(kstr.get_key_events());
+        assert!(csv.contains("a,10000,\nb,10000.400,\n"));
+        let parsed = KeystrokeLogger::deserialize_events_csv(&csv).unwrap();
+        assert_eq!(parsed[1].timestamp_us(), 10_000_400);
+    }
+
+    #[test]
+    fn decision_policy_veto_wins() {
+        let session = |jitter: u128| {
+            let mut kstr = KeystrokeLogger::new();
+            for i in 0..30 {
+                kstr.add_key_event(KeyEvent::new(10000 + 150 * i + jitter * (i % 2), ['a', 'b', 'c'][i as usize % 3]));
+            }
+            kstr
+        };
+        let enrollment = session(0);
+        let profile = UserProfile::new(30, 10, 10.0, &KeynomeAuthenticatorDiffParams::default(),
+                                       &enrollment.compute_digraph_statistics());
+        let mut policy = DecisionPolicy::new(FeatureEnsemble::from_enrollment(&enrollment), 1.0);
+
+        // a perfectly regular sample matches the (equally regular) profile
+        assert_eq!(policy.decide(&profile, &session(0)).verdict, AuthVerdict::Accept);
+
+        policy.add_veto(Box::new(SyntheticTimingVeto { min_interval_std_ms: 5.0 }));
+        let decision = policy.decide(&profile, &session(0));
+        assert_eq!(decision.verdict, AuthVerdict::Reject(RejectReason::LivenessFailed));
+        assert_eq!(decision.vetoed_by.as_deref(), Some("synthetic_timing"));
+        assert_eq!(policy.decide(&profile, &session(20)).vetoed_by, None);
+    }
+
+    #[test]
+    fn decision_policy_replay_veto() {
+        let mut rng = SeededRng::new(5);
+        let mut typed = |start_ms: u128| {
+            let mut kstr = KeystrokeLogger::new();
+            let mut ts = start_ms;
+            for key in "the quick brown fox jumps".chars() {
+                kstr.add_key_event(KeyEvent::new(ts, key));
+                ts += 100 + (rng.next_f64() * 80.0) as u128;
+            }
+            kstr
+        };
+        let enrollment = typed(10000);
+        let veto = ReplayVeto::new(enrollment.get_key_events(), 6, 1000);
+
+        // the middle of the enrollment, played back an hour later
+        let mut replayed = KeystrokeLogger::new();
+        for ev in enrollment.get_key_events().range(5..20) {
+            replayed.add_key_event(KeyEvent::from_micros(ev.timestamp_us + 3_600_000_000, ev.key));
+        }
+        assert!(veto.vetoes(&replayed));
+        assert_eq!(veto.reason(), RejectReason::ReplaySuspected);
+
+        // the same text typed again, with fresh timing
+        assert!(!veto.vetoes(&typed(50000)));
+    }
+
+    #[test]
+    fn keystroke_logger_from_iter_and_extend() {
+        let keys = vec![('a', 10000), ('b', 10100), ('a', 10300)];
+        let mut kstr: KeystrokeLogger = keys.into_iter().collect();
+        kstr.extend("bab".chars().zip([10400, 10600, 10700].iter().cloned()));
+
+        let timestamps: Vec<u128> = kstr.get_key_events().iter().map(|e| e.timestamp_ms()).collect();
+        assert_eq!(timestamps, vec![10000, 10100, 10300, 10400, 10600, 10700]);
+        let stats = kstr.compute_digraph_statistics();
+        assert_numerically_similar!(0.0001, stats[&('a', 'b')].mean, 100.0);
+        assert_numerically_similar!(0.0001, stats[&('b', 'a')].mean, 200.0);
+    }
+
+    #[test]
+    fn quantile_sketch_approximates_median_and_iqr() {
+        let mut rng = SeededRng::new(11);
+        let mut sketch = QuantileSketch::new();
+        let mut samples: Vec<f64> = Vec::new();
+        for _ in 0..2000 {
+            let x = 150.0 + 30.0 * rng.next_gaussian();
+            sketch.observe(x);
+            samples.push(x);
+        }
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let exact_median = samples[1000];
+        let exact_iqr = samples[1500] - samples[500];
+
+        assert!((sketch.median().unwrap() - exact_median).abs() < 0.05 * exact_iqr);
+        assert!((sketch.iqr().unwrap() - exact_iqr).abs() < 0.05 * exact_iqr);
+
+        // summary-only logging now reports quantiles as well
+        let mut kstr = KeystrokeLogger::new();
+        kstr.set_summary_only();
+        for (i, ms) in [100, 110, 90, 120, 100, 80].iter().enumerate() {
+            kstr.add_key_event(KeyEvent::new(10000 * (i as u128 + 1), 'a'));
+            kstr.add_key_event(KeyEvent::new(10000 * (i as u128 + 1) + ms, 'b'));
+        }
+        assert!(kstr.compute_digraph_statistics()[&('a', 'b')].has_quantiles());
+    }
+
+    #[test]
+    fn evicted_digraphs_can_be_retained() {
+        let mut window = KeystrokeLogger::new();
+        window.set_events_limit(3);
+        let mut retained = KeystrokeLogger::new();
+        retained.set_events_limit(3);
+        retained.retain_evicted_digraphs(true);
+        for kstr in [&mut window, &mut retained].iter_mut() {
+            for (ts, key) in [(0, 'a'), (100, 'b'), (1000, 'a'), (1120, 'b'), (2000, 'c'), (2100, 'd')].iter() {
+                kstr.add_key_event(KeyEvent::new(*ts, *key));
+            }
+        }
+
+        assert!(!window.compute_digraph_statistics().contains_key(&('a', 'b')));
+        let stats = retained.compute_digraph_statistics();
+        assert_eq!(stats[&('a', 'b')].size_samples, 2);
+        assert_eq!(stats[&('a', 'b')].mean, 110.0);
+        assert_eq!(retained.get_key_events().len(), 3);
+    }
+
+    #[test]
+    fn skipgram_statistics() {
+        let mut kstr = KeystrokeLogger::new();
+        for (ts, key) in [(0, 'a'), (100, 'b'), (250, 'c'), (1000, 'a'), (1120, 'b'), (1300, 'c')].iter() {
+            kstr.add_key_event(KeyEvent::new(*ts, *key));
+        }
+
+        let skip1 = kstr.compute_skipgram_statistics(1);
+        let digraphs = kstr.compute_digraph_statistics();
+        assert_eq!(skip1.len(), digraphs.len());
+        for (k, v) in digraphs.iter() {
+            assert_eq!(skip1[k].size_samples, v.size_samples);
+            assert!((skip1[k].mean - v.mean).abs() < 1e-9);
+        }
+
+        let skip2 = kstr.compute_skipgram_statistics(2);
+        assert_eq!(skip2[&('a', 'c')].size_samples, 2);
+        assert_eq!(skip2[&('a', 'c')].mean, 275.0);
+    }
+
+    #[test]
+    fn user_profile_frozen() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        let mut profile = UserProfile::new(100, 50, 10.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        profile.frozen = true;
+
+        let mut profile = UserProfile::deserialize(&profile.serialize());
+        assert!(profile.frozen);
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(10, 200.0, 10.0));
+        stats_sample.insert(('b', 'c'), DigraphStats::new(10, 200.0, 10.0));
+        profile.update_with_sample(&stats_sample, 0.5);
+        assert_eq!(profile.stats.len(), 1);
+        assert_eq!(profile.stats[&('a', 'b')].mean, 100.0);
+
+        // merging would change the baseline too, from either side
+        let session = UserProfile::new(100, 50, 10.0, &KeynomeAuthenticatorDiffParams::default(), &stats_sample);
+        assert!(session.merge(&profile).is_err());
+        assert!(profile.merge(&session).is_err());
+        assert_eq!(profile.stats.len(), 1);
+    }
+
+    #[test]
+    fn user_profile_migrate_legacy() {
+        let stats_legacy = r#"{"a-b":"{\"size_samples\":10,\"mean\":100.0,\"std\":10.0}","--a":"{\"size_samples\":4,\"mean\":80.0,\"std\":5.0}"}"#;
+        let mut legacy = HashMap::new();
+        legacy.insert("n_profile", "100".to_string());
+        legacy.insert("n_sample", "50".to_string());
+        legacy.insert("diff_base", "12.5".to_string());
+        legacy.insert("diff_params", "{}".to_string());
+        legacy.insert("stats", stats_legacy.to_string());
+        let legacy = serde_json::to_string(&legacy).unwrap();
+        assert!(UserProfile::is_legacy_format(&legacy));
+
+        let path = std::env::temp_dir().join(format!("keynome-migrate-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, &legacy).unwrap();
+        assert!(UserProfile::migrate_legacy(path).unwrap());
+        assert!(!UserProfile::migrate_legacy(path).unwrap());
+
+        let migrated = std::fs::read_to_string(path).unwrap();
+        assert!(!UserProfile::is_legacy_format(&migrated));
+        let profile = UserProfile::deserialize(&migrated);
+        assert_eq!(profile.diff_base, 12.5);
+        assert_eq!(profile.stats[&('a', 'b')].mean, 100.0);
+        assert_eq!(profile.stats[&('-', 'a')].size_samples, 4);
+        assert_eq!(std::fs::read_to_string(format!("{}.legacy", path)).unwrap(), legacy);
+
+        // invalid legacy files are left alone
+        std::fs::write(path, legacy.replace("12.5", "fast")).unwrap();
+        assert!(UserProfile::migrate_legacy(path).is_err());
+        assert!(UserProfile::is_legacy_format(&std::fs::read_to_string(path).unwrap()));
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(format!("{}.legacy", path)).unwrap();
     }
 
-    pub fn compute_digraph_statistics(&self) -> HashMap<Digraph, DigraphStats> {
-        let mut samples: HashMap<Digraph, Vec<f64>> = HashMap::new();
-        for i in 1..self.events.len() {
-            let ev1 = &self.events[i-1];
-            let ev2 = &self.events[i];
+    #[test]
+    fn continuous_authenticator_acceptance_bands() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('b', 'a'), DigraphStats::new(10, 300.0, 40.0));
+        let diff_params = KeynomeAuthenticatorDiffParams {
+            acceptance_bands: Some(AcceptanceBands { width: 2.0, min_fraction: 1.0 }),
+            ..Default::default()
+        };
 
-            let k = (ev1.key, ev2.key);
-            let v = (ev2.timestamp_ms - ev1.timestamp_ms) as f64;
-            match samples.get_mut(&k) {
-                Some(arr) => { arr.push(v); },
-                None => { samples.insert(k, vec![v]); },
-            }
-        }
+        // b-a is far off in ms but within its own, wider band
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(5, 110.0, 5.0));
+        stats_sample.insert(('b', 'a'), DigraphStats::new(5, 370.0, 5.0));
+        assert_eq!(KeynomeAuthenticator::compute_band_fraction(&stats, &stats_sample, &diff_params, 2.0), Some(1.0));
+        stats_sample.insert(('a', 'b'), DigraphStats::new(5, 130.0, 5.0));
+        assert_eq!(KeynomeAuthenticator::compute_band_fraction(&stats, &stats_sample, &diff_params, 2.0), Some(0.5));
+        assert_eq!(KeynomeAuthenticator::compute_band_fraction(&stats, &HashMap::new(), &diff_params, 2.0), None);
 
-        let mut stats: HashMap<Digraph, DigraphStats> = HashMap::new();
-        for (k, v) in samples.iter() {
-            if v.len() >= 2 {
-                let mean = statistical::mean(v);
-                let std = statistical::standard_deviation(v, Some(mean));
-                stats.insert(*k, DigraphStats { size_samples: v.len(), mean, std });
-            }
+        let profile = UserProfile::new(12, 6, 1.0, &diff_params, &stats);
+        let events: VecDeque<KeyEvent> = (0..6)
+            .map(|i| KeyEvent::new(10000 + 470 * (i / 2) + 100 * (i % 2), ['a', 'b'][i as usize % 2]))
+            .collect();
+        let mut auth = ContinuousAuthenticator::new(profile, 1.0);
+        let mut verdict = AuthVerdict::Warming;
+        for ev in events.iter() {
+            verdict = auth.add_key_event(ev.clone());
         }
+        assert_eq!(verdict, AuthVerdict::Accept);
 
-        stats
+        // the one-shot authenticator decides by the same bands, although the diff is far
+        // beyond diff_base * multiplier
+        let result = KeynomeAuthenticator::new(1.0, 1.0, &diff_params, &stats).authenticate_with_score(&events);
+        assert!(result.n_comparisons > 0 && result.diff > result.threshold);
+        assert_eq!((result.accepted, result.confidence), (true, 1.0));
     }
 
-    pub fn serialize_digraph_statistics(stats: &HashMap<Digraph, DigraphStats>) -> String {
-        let mut str_keyed_map: HashMap<String, String> = HashMap::new();
-        for (k, v) in stats.iter() {
-            let key = format!("{}-{}", k.0, k.1);
-            let value = serde_json::to_string(v).unwrap();
-            str_keyed_map.insert(key, value);
+    #[test]
+    fn clock_granularity_estimate() {
+        let mut events = VecDeque::new();
+        assert_eq!(estimate_clock_granularity_ms(&events), None);
+        for ts in [1000, 1120, 1120, 1250, 1410].iter() {
+            events.push_back(KeyEvent::new(*ts, 'a'));
         }
-        serde_json::to_string(&str_keyed_map).unwrap()
+        assert_eq!(estimate_clock_granularity_ms(&events), Some(10.0));
+
+        events.push_back(KeyEvent::from_micros(1_410_500, 'a'));
+        assert_eq!(estimate_clock_granularity_ms(&events), Some(0.5));
     }
 
-    pub fn deserialize_digraph_statistics(serialized: &str) -> HashMap<Digraph, DigraphStats> {
-        let mut stats: HashMap<Digraph, DigraphStats> = HashMap::new();
-        let str_keyed_map: HashMap<String, String> = serde_json::from_str(serialized).unwrap();
+    #[test]
+    fn compute_diff_frequency_weighting() {
+        let mut stats_profile = HashMap::new();
+        stats_profile.insert(('t', 'h'), DigraphStats::new(90, 100.0, 10.0));
+        stats_profile.insert(('q', 'z'), DigraphStats::new(10, 100.0, 10.0));
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('t', 'h'), DigraphStats::new(5, 110.0, 10.0));
+        stats_sample.insert(('q', 'z'), DigraphStats::new(5, 110.0, 10.0));
 
-        for (k, v) in str_keyed_map.iter() {
-            let vec_digraph: Vec<&str> = k.split('-').collect();
-            let vec_k1: Vec<char> = vec_digraph[0].chars().collect();
-            let vec_k2: Vec<char> = vec_digraph[1].chars().collect();
+        let uniform = KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &Default::default());
+        assert_eq!(uniform, 20.0);
 
-            let digraph = (vec_k1[0], vec_k2[0]);
-            let digraph_stats: DigraphStats = serde_json::from_str(v).unwrap();
+        let diff_params = KeynomeAuthenticatorDiffParams {
+            frequency_weighting: FrequencyWeighting::ProfileCounts,
+            ..Default::default()
+        };
+        let diff = KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params);
+        assert_numerically_similar!(1e-9, diff, 10.0 * ((1.0 + 1.0 / 0.9f64).ln() + 11.0f64.ln()));
 
-            stats.insert(digraph, digraph_stats);
+        // the rare digraph dominates once it alone differs
+        stats_sample.insert(('t', 'h'), DigraphStats::new(5, 100.0, 10.0));
+        let rare_only = KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params);
+        assert!(rare_only > 0.75 * diff);
+
+        let mut table = HashMap::new();
+        table.insert(('t', 'h'), 0.03);
+        let diff_params = KeynomeAuthenticatorDiffParams {
+            frequency_weighting: FrequencyWeighting::Table,
+            frequency_table: Some(table),
+            ..Default::default()
+        };
+        let diff = KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params);
+        assert_numerically_similar!(1e-9, diff, 10.0 * (1.0 + 1.0 / 0.03f64).ln());
+
+        // a table without a positive frequency must not zero the diff, the weights stay uniform
+        let mut table = HashMap::new();
+        table.insert(('t', 'h'), 0.0);
+        for table in [HashMap::new(), table].iter() {
+            let diff_params = KeynomeAuthenticatorDiffParams {
+                frequency_weighting: FrequencyWeighting::Table,
+                frequency_table: Some(table.clone()),
+                ..Default::default()
+            };
+            assert_eq!(KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params), 10.0);
         }
-        stats
     }
-}
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct KeynomeAuthenticatorDiffParams {
-    pub dispersion: bool,
-    pub min_instances: u32,
-    pub max_comparisons: u32,
-}
+    #[test]
+    fn degenerate_samples_are_skipped() {
+        assert!(summarize(&[]).is_none());
+        assert!(summarize(&[120.0]).is_none());
+        assert!(summarize(&[f64::NAN, 120.0, f64::INFINITY]).is_none());
+        assert_eq!(summarize(&[f64::NAN, 100.0, 120.0]).unwrap().mean, 110.0);
 
-pub struct KeynomeAuthenticator<'a, 'b> {
-    pub diff_base: f64,
-    pub multiplier: f64,
-    pub diff_params: &'a KeynomeAuthenticatorDiffParams,
-    pub stats: &'b HashMap<Digraph, DigraphStats>,
-}
+        let mut samples: HashMap<Digraph, Vec<f64>> = HashMap::new();
+        samples.insert(('a', 'b'), Vec::new());
+        samples.insert(('b', 'c'), vec![90.0]);
+        samples.insert(('c', 'd'), vec![90.0, 90.0]);
+        let stats = summarize_samples(&samples);
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[&('c', 'd')].std, 0.0);
 
-impl<'a, 'b> KeynomeAuthenticator<'a, 'b> {
-    pub fn new(diff_base: f64, multiplier: f64, diff_params: &'a KeynomeAuthenticatorDiffParams,
-               stats: &'b HashMap<Digraph, DigraphStats>) -> KeynomeAuthenticator<'a, 'b> {
-        KeynomeAuthenticator { diff_base, multiplier, diff_params, stats }
+        let mut running = RunningStats::new();
+        running.push(f64::NAN);
+        running.push(100.0);
+        assert!(running.to_stats().is_none());
+
+        // every dwell filtered out by the band
+        let mut kstr = KeystrokeLogger::new();
+        kstr.set_dwell_band(50.0, 100.0);
+        kstr.add_key_event(KeyEvent::new(0, 'a').with_release(10));
+        kstr.add_key_event(KeyEvent::new(100, 'a').with_release(500));
+        assert!(kstr.compute_dwell_statistics().is_empty());
     }
 
-    pub fn compute_diff(
-        stats_profile: &HashMap<Digraph, DigraphStats>, stats_sample: &HashMap<Digraph, DigraphStats>,
-        diff_params: &KeynomeAuthenticatorDiffParams) -> f64 {
-        
-        let mut diff: f64 = 0.0;
-        let mut n_comparisons: u32 = 0;
+    #[test]
+    fn user_profile_stats_only_serialization() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('b', 'c'), DigraphStats::new(4, 150.0, 20.0));
+        let mut profile = UserProfile::new(100, 50, 12.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        profile.set_genuine_diffs(&[10.0, 14.0]);
 
-        for (k, v) in stats_profile.iter() {
-            if v.size_samples < diff_params.min_instances as usize {
-                continue;
-            }
+        let serialized = profile.serialize_stats_only();
+        let value: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert!(value.get("diff_base").is_none());
+        assert_eq!(value["a-b"]["mean"], 100.0);
 
-            if let Some(vs) = stats_sample.get(k) {
-                diff = diff + ((v.mean - vs.mean).abs() / (if diff_params.dispersion { 0.001 + v.std } else { 1.0 }));
-                n_comparisons = n_comparisons + 1;
-            }
+        let diff_params = KeynomeAuthenticatorDiffParams { min_instances: 5, ..Default::default() };
+        let rebuilt = UserProfile::from_serialized_stats(200, 20, 30.0, &diff_params, &serialized);
+        assert_eq!((rebuilt.n_profile, rebuilt.n_sample, rebuilt.diff_base), (200, 20, 30.0));
+        assert_eq!(rebuilt.diff_params.min_instances, 5);
+        assert_eq!(rebuilt.stats.len(), 2);
+        assert_eq!(rebuilt.stats[&('b', 'c')].std, 20.0);
+        assert!(rebuilt.genuine_diffs.is_empty());
+        assert!(UserProfile::try_from_serialized_stats(200, 20, 30.0, &diff_params, "{").is_err());
+    }
 
-            if n_comparisons >= diff_params.max_comparisons {
-                break;
-            }
+    #[test]
+    fn continuous_authenticator_layout_change() {
+        let mut stats = HashMap::new();
+        for k in [('t', 'h'), ('h', 'e'), ('e', 't')].iter() {
+            stats.insert(*k, DigraphStats::new(10, 100.0, 10.0));
         }
-        diff
-    }
+        let profile = UserProfile::new(12, 6, 10.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+        auth.layout_change_ratio = Some(0.5);
+        assert_eq!(auth.novel_digraph_ratio(), None);
 
-    pub fn compute_diff_base(
-        events: &VecDeque<KeyEvent>, n_profile: usize, n_sample: usize,
-        diff_params: &KeynomeAuthenticatorDiffParams) -> Option<f64> {
+        // "the" typed under another layout, where the h key produces a j
+        let mut verdict = AuthVerdict::Warming;
+        for (i, key) in "tjetje".chars().enumerate() {
+            verdict = auth.add_key_event(KeyEvent::new(10000 + 100 * i as u128, key));
+        }
+        assert_numerically_similar!(1e-9, auth.novel_digraph_ratio().unwrap(), 0.8);
+        assert_eq!(verdict, AuthVerdict::Reject(RejectReason::LayoutChanged));
 
-        if n_profile > events.len() {
-            return None;
-        } else if n_profile % n_sample != 0 {
-            return None;
+        for (i, key) in "thethe".chars().enumerate() {
+            verdict = auth.add_key_event(KeyEvent::new(20000 + 100 * i as u128, key));
         }
+        assert_eq!(auth.novel_digraph_ratio(), Some(0.0));
+        assert_eq!(verdict, AuthVerdict::Accept);
+    }
 
-        let vec_events: Vec<&KeyEvent> = events.into_iter().collect();
+    #[test]
+    fn skip_post_correction_digraphs() {
         let mut kstr = KeystrokeLogger::new();
-        for ev in &vec_events[(events.len()-n_profile)..] {
-            kstr.add_key_event(KeyEvent { timestamp_ms: ev.timestamp_ms, key: ev.key });
+        // "tha<BS>e then", the e-space digraph after the correction is slow
+        let keys = ['t', 'h', 'a', BACKSPACE, 'e', ' ', 't', 'h', 'e', ' '];
+        let times = [0, 100, 200, 400, 600, 1200, 1300, 1400, 1500, 1600];
+        for (ts, key) in times.iter().zip(keys.iter()) {
+            kstr.add_key_event(KeyEvent::new(*ts, *key));
         }
-        let stats = kstr.compute_digraph_statistics();
+        assert_eq!(kstr.compute_digraph_statistics()[&('e', ' ')].mean, 350.0);
 
-        let mut diff_base: f64 = 0.0;
-        for i in 0..n_profile/n_sample {
-            let idx_start = (events.len() - n_profile) + n_sample * i;
-            let idx_end = (events.len() - n_profile) + n_sample * (i + 1);
+        kstr.set_skip_post_correction(true);
+        let stats = kstr.compute_digraph_statistics();
+        assert!(!stats.contains_key(&('e', ' ')));
+        assert_eq!(stats[&('t', 'h')].size_samples, 2);
 
-            let mut kstr_sample = KeystrokeLogger::new();
-            for ev in &vec_events[idx_start..idx_end] {
-                kstr_sample.add_key_event(KeyEvent { timestamp_ms: ev.timestamp_ms, key: ev.key });
+        // every n-graph containing the skipped digraph goes with it
+        let mut twice = KeystrokeLogger::new();
+        for offset in [0, 2000].iter() {
+            for (ts, key) in times.iter().zip(keys.iter()) {
+                twice.add_key_event(KeyEvent::new(offset + ts, *key));
             }
-
-            let stats_sample = kstr_sample.compute_digraph_statistics();
-            diff_base = diff_base + KeynomeAuthenticator::compute_diff(&stats, &stats_sample, diff_params);
         }
+        assert!(twice.compute_trigraph_statistics().contains_key(&(BACKSPACE, 'e', ' ')));
+        twice.set_skip_post_correction(true);
+        let trigraphs = twice.compute_trigraph_statistics();
+        assert!(!trigraphs.contains_key(&(BACKSPACE, 'e', ' ')));
+        assert!(!trigraphs.contains_key(&('e', ' ', 't')));
+        assert_eq!(trigraphs.len(), twice.compute_ngraph_statistics(3).len());
+        let skipgrams = twice.compute_skipgram_statistics(1);
+        assert!(twice.compute_digraph_statistics().keys().all(|k| skipgrams.contains_key(k)));
+        assert!(!twice.compute_skipgram_statistics(2).contains_key(&(BACKSPACE, ' ')));
 
-        diff_base = diff_base / (n_profile / n_sample) as f64;
-        Some(diff_base)
-    }
-
-    pub fn authenticate(&self, events: &VecDeque<KeyEvent>) -> bool {
-        true
+        // summary-only and retained evictions skip the same digraph
+        let mut summary = KeystrokeLogger::new();
+        summary.set_skip_post_correction(true);
+        summary.set_summary_only();
+        let mut retained = KeystrokeLogger::new();
+        retained.set_skip_post_correction(true);
+        retained.set_events_limit(2);
+        retained.retain_evicted_digraphs(true);
+        for (ts, key) in times.iter().zip(keys.iter()) {
+            summary.add_key_event(KeyEvent::new(*ts, *key));
+            retained.add_key_event(KeyEvent::new(*ts, *key));
+        }
+        for other in [summary.compute_digraph_statistics(), retained.compute_digraph_statistics()].iter() {
+            assert_eq!(other.len(), stats.len());
+            for (k, v) in stats.iter() {
+                assert_eq!(other[k].size_samples, v.size_samples);
+                assert_eq!(other[k].mean, v.mean);
+            }
+        }
     }
-}
 
-pub struct UserProfile {
-    pub n_profile: u32,
-    pub n_sample: u32,
-    pub diff_base: f64,
-    pub diff_params: KeynomeAuthenticatorDiffParams,
-    pub stats: HashMap<Digraph, DigraphStats>,
-}
+    #[test]
+    fn user_profile_signature() {
+        let mut stats = HashMap::new();
+        for (i, c) in "abcdefghijkl".chars().enumerate() {
+            stats.insert((c, 'z'), DigraphStats::new(10, 100.0 + i as f64, 1.0 + i as f64));
+        }
+        stats.insert(('é', 'z'), DigraphStats::new(10, 100.0, 0.1));
+        let profile = UserProfile::new(100, 50, 10.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
 
-impl UserProfile {
-    pub fn new(n_profile: u32, n_sample: u32, diff_base: f64,
-           _diff_params: &KeynomeAuthenticatorDiffParams,
-           _stats: &HashMap<Digraph, DigraphStats>) -> UserProfile {
+        let signature = profile.signature();
+        assert_eq!(signature.entries.len(), SIGNATURE_SIZE);
+        assert_eq!(signature.entries[0], (('a', 'z'), 100));
+        let bytes = signature.to_bytes();
+        assert_eq!(bytes.len(), 4 * SIGNATURE_SIZE);
+        assert_eq!(Signature::from_bytes(&bytes), Some(signature.clone()));
 
-        let diff_params = (*_diff_params).clone();
-        let mut stats: HashMap<Digraph, DigraphStats> = HashMap::new();
-        for (k, v) in _stats.iter() {
-            stats.insert(*k, (*v).clone());
-        }
-        UserProfile { n_profile, n_sample, diff_base, diff_params, stats }
+        let shifted = Signature { entries: signature.entries.iter().map(|(k, m)| (*k, m + 20)).collect() };
+        assert_eq!(signature_distance(&signature, &signature), Some(0.0));
+        assert_eq!(signature_distance(&signature, &shifted), Some(20.0));
+        assert_eq!(signature_distance(&signature, &Signature { entries: vec![(('q', 'q'), 90)] }), None);
     }
 
-    pub fn serialize(&self) -> String {
-        let mut obj = HashMap::new();
+    #[test]
+    fn digraph_discriminability_ranking() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('b', 'c'), DigraphStats::new(10, 210.0, 10.0));
+        stats.insert(('c', 'd'), DigraphStats::new(10, 120.0, 10.0));
+        stats.insert(('d', 'e'), DigraphStats::new(10, 100.0, 10.0));
+        let profile = UserProfile::new(100, 50, 10.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
 
-        obj.insert("n_profile", format!("{}", self.n_profile));
-        obj.insert("n_sample", format!("{}", self.n_sample));
-        obj.insert("diff_base", format!("{}", self.diff_base));
-        obj.insert("diff_params", serde_json::to_string(&self.diff_params).unwrap());
-        obj.insert("stats", KeystrokeLogger::serialize_digraph_statistics(&self.stats));
+        let mut stats_population = HashMap::new();
+        stats_population.insert(('a', 'b'), DigraphStats::new(1000, 100.0, 10.0));
+        stats_population.insert(('b', 'c'), DigraphStats::new(1000, 200.0, 10.0));
+        stats_population.insert(('c', 'd'), DigraphStats::new(1000, 150.0, 10.0));
 
-        serde_json::to_string(&obj).unwrap()
+        let scores = digraph_discriminability(&profile, &stats_population);
+        assert_eq!(scores, vec![(('c', 'd'), -3.0), (('b', 'c'), 1.0), (('a', 'b'), 0.0)]);
     }
 
-    pub fn deserialize(serialized: &str) -> UserProfile {
-        let str_keyed_map: HashMap<String, String> = serde_json::from_str(serialized).unwrap();
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn user_profile_encryption() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        let profile = UserProfile::new(100, 50, 12.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+
+        let encrypted = profile.to_encrypted_bytes("correct horse").unwrap();
+        assert!(UserProfile::is_encrypted(&encrypted));
+        assert!(!UserProfile::is_encrypted(profile.serialize().as_bytes()));
+        assert_ne!(encrypted, profile.to_encrypted_bytes("correct horse").unwrap());
 
-        let n_profile: u32 = str_keyed_map.get("n_profile").unwrap().parse().unwrap();
-        let n_sample: u32 = str_keyed_map.get("n_sample").unwrap().parse().unwrap();
-        let diff_base: f64 = str_keyed_map.get("diff_base").unwrap().parse().unwrap();
-        let diff_params: KeynomeAuthenticatorDiffParams = serde_json::from_str(
-            &str_keyed_map.get("diff_params").unwrap()).unwrap();
-        let stats: HashMap<Digraph, DigraphStats> = KeystrokeLogger::deserialize_digraph_statistics(
-            &str_keyed_map.get("stats").unwrap());
+        let decrypted = UserProfile::from_encrypted_bytes(&encrypted, "correct horse").unwrap();
+        assert_eq!(decrypted.diff_base, 12.0);
+        assert_eq!(decrypted.stats[&('a', 'b')].mean, 100.0);
 
-        UserProfile { n_profile, n_sample, diff_base, diff_params, stats }
+        assert!(UserProfile::from_encrypted_bytes(&encrypted, "wrong").is_err());
+        let mut tampered = encrypted.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(UserProfile::from_encrypted_bytes(&tampered, "correct horse").is_err());
+        assert!(UserProfile::from_encrypted_bytes(&encrypted[..20], "correct horse").is_err());
     }
-}
 
+    #[test]
+    fn merge_digraph_statistics_with_and_without_samples() {
+        let session = |offset: u128, gaps: &[u128]| {
+            let mut kstr = KeystrokeLogger::new();
+            let mut ts = offset;
+            for (i, gap) in gaps.iter().enumerate() {
+                kstr.add_key_event(KeyEvent::new(ts, 'a'));
+                kstr.add_key_event(KeyEvent::new(ts + gap, 'b'));
+                ts += 1000 * (i as u128 + 1);
+            }
+            kstr
+        };
+        let a = session(0, &[100, 120, 90]);
+        let b = session(100000, &[150, 160]);
+        let (stats_a, stats_b) = (a.compute_digraph_statistics(), b.compute_digraph_statistics());
+        let exact = summarize(&[100.0, 120.0, 90.0, 150.0, 160.0]).unwrap();
+
+        let combined = stats_a[&('a', 'b')].combine(&stats_b[&('a', 'b')]);
+        assert_eq!(combined.size_samples, 5);
+        assert_numerically_similar!(1e-9, combined.mean, exact.mean);
+        assert_numerically_similar!(1e-9, combined.std, exact.std);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let (samples_a, samples_b) = (a.compute_digraph_samples(), b.compute_digraph_samples());
+        assert_eq!(samples_a[&('a', 'b')], vec![100.0, 120.0, 90.0]);
+        let merged = merge_digraph_statistics(&stats_a, &stats_b, Some(&samples_a), Some(&samples_b));
+        assert_eq!(merged[&('a', 'b')].mean, exact.mean);
+        assert_eq!(merged[&('a', 'b')].std, exact.std);
 
-    #[test]
-    fn keystroke_logger_instantiation() {
-        let _ = KeystrokeLogger::new();
+        // b-a only exists in the first session, and is kept as is
+        let merged = merge_digraph_statistics(&stats_a, &stats_b, None, None);
+        assert_eq!(merged[&('b', 'a')].size_samples, stats_a[&('b', 'a')].size_samples);
+        assert_numerically_similar!(1e-9, merged[&('a', 'b')].std, exact.std);
     }
 
     #[test]
-    fn keystroke_logger_preserving_keystroke_history() {
-        let mut kstr = KeystrokeLogger::new();
-        kstr.add_keystroke('a');
-        kstr.add_keystroke('b');
-        kstr.add_keystroke('c');
+    fn continuous_authenticator_comparison_tracking() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('b', 'a'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('c', 'd'), DigraphStats::new(10, 100.0, 10.0));
+        let profile = UserProfile::new(12, 6, 10.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        let mut auth = ContinuousAuthenticator::new(profile, 1.5);
+        assert!(auth.comparison_counts().is_none());
 
-        let events = kstr.get_key_events();
-        let keystrokes: Vec<char> = events.into_iter().map(|e| e.key).collect();
-        assert_eq!(keystrokes, vec!['a', 'b', 'c']);
+        auth.set_comparison_tracking(true);
+        for i in 0..8 {
+            auth.add_key_event(KeyEvent::new(10000 + 100 * i, ['a', 'b'][i as usize % 2]));
+        }
+        // the window is full from the 6th keystroke on, three decisions
+        let counts = auth.comparison_counts().unwrap();
+        assert_eq!(counts[&('a', 'b')], 3);
+        assert_eq!(counts[&('b', 'a')], 3);
+        assert!(!counts.contains_key(&('c', 'd')));
     }
 
     #[test]
-    fn keystroke_logger_events_limit() {
-        let mut kstr = KeystrokeLogger::new();
-        kstr.set_events_limit(123);
+    fn user_profile_try_deserialize_errors() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats::new(10, 100.0, 10.0));
+        let profile = UserProfile::new(100, 50, 12.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
+        let serialized = profile.serialize();
+        assert!(UserProfile::try_deserialize(&serialized).is_ok());
 
-        for _ in 0..200 {
-            kstr.add_keystroke('a');
-        }
-        assert_eq!(kstr.get_key_events().len(), 123);
+        // bad JSON, e.g. a truncated file
+        let truncated = &serialized[..serialized.len() / 2];
+        assert!(matches!(UserProfile::try_deserialize(truncated), Err(ProfileError::Json(_))));
+
+        let mut value: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        value.as_object_mut().unwrap().remove("n_sample");
+        assert_eq!(UserProfile::try_deserialize(&value.to_string()).err(),
+                   Some(ProfileError::MissingField("n_sample".to_string())));
+
+        let mut value: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        value["diff_base"] = serde_json::json!("fast");
+        assert_eq!(UserProfile::try_deserialize(&value.to_string()).err(),
+                   Some(ProfileError::InvalidNumber("diff_base".to_string(), "\"fast\"".to_string())));
+
+        // the same failures in the legacy layout
+        let mut legacy = HashMap::new();
+        legacy.insert("n_profile", "100".to_string());
+        legacy.insert("n_sample", "50".to_string());
+        legacy.insert("diff_base", "fast".to_string());
+        legacy.insert("diff_params", "{}".to_string());
+        legacy.insert("stats", "{}".to_string());
+        assert_eq!(UserProfile::try_deserialize(&serde_json::to_string(&legacy).unwrap()).err(),
+                   Some(ProfileError::InvalidNumber("diff_base".to_string(), "fast".to_string())));
+        legacy.remove("n_sample");
+        assert_eq!(UserProfile::try_deserialize(&serde_json::to_string(&legacy).unwrap()).err(),
+                   Some(ProfileError::MissingField("n_sample".to_string())));
     }
 
     #[test]
-    fn keystroke_logger_time_difference() {
-        let delays: Vec<u64> = vec![12, 34, 56];
+    fn user_profile_dash_digraph_roundtrip() {
+        let mut stats = HashMap::new();
+        stats.insert(('-', 'a'), DigraphStats::new(10, 100.0, 10.0));
+        stats.insert(('a', '-'), DigraphStats::new(12, 120.0, 12.0));
+        stats.insert(('-', '-'), DigraphStats::new(14, 140.0, 14.0));
+        stats.insert(('\t', 'é'), DigraphStats::new(16, 160.0, 16.0));
+        let profile = UserProfile::new(100, 50, 12.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
 
-        let mut kstr = KeystrokeLogger::new();
-        kstr.add_keystroke('a');
-        thread::sleep(Duration::from_millis(delays[0]));
-        kstr.add_keystroke('b');
-        thread::sleep(Duration::from_millis(delays[1]));
-        kstr.add_keystroke('c');
-        thread::sleep(Duration::from_millis(delays[2]));
-        kstr.add_keystroke('d');
+        let deserialized = UserProfile::deserialize(&profile.serialize());
+        assert_eq!(deserialized.stats.len(), 4);
+        assert_eq!(deserialized.stats[&('-', 'a')].size_samples, 10);
+        assert_eq!(deserialized.stats[&('a', '-')].size_samples, 12);
+        assert_eq!(deserialized.stats[&('-', '-')].size_samples, 14);
+        assert_eq!(deserialized.stats[&('\t', 'é')].size_samples, 16);
 
-        let events = kstr.get_key_events();
-        assert_numerically_similar!(1.0, (events[1].timestamp_ms - events[0].timestamp_ms) as f64, delays[0] as f64);
-        assert_numerically_similar!(1.0, (events[2].timestamp_ms - events[1].timestamp_ms) as f64, delays[1] as f64);
-        assert_numerically_similar!(1.0, (events[3].timestamp_ms - events[2].timestamp_ms) as f64, delays[2] as f64);
+        let table = KeystrokeLogger::deserialize_digraph_statistics(&KeystrokeLogger::serialize_digraph_statistics(&stats));
+        assert_eq!(table[&('-', 'a')].size_samples, 10);
+        assert_eq!(table[&('a', '-')].size_samples, 12);
     }
 
     #[test]
-    fn keystroke_logger_digraph_statistics() {
-        let mut kstr = KeystrokeLogger::new();
-
-        // a-b digraphs, diffs = [1000, 2000, 3000], mean = 2000.0, std = 1000.0
-        // b-a digraphs, diffs = [1000, 1000], mean = 1000.0, std = 0.0
-        kstr.add_key_event(KeyEvent { timestamp_ms: 10000, key: 'a' });
-        kstr.add_key_event(KeyEvent { timestamp_ms: 11000, key: 'b' });
+    fn digraph_stats_median_and_iqr() {
+        // sorted: [90, 100, 110, 120, 130, 3000], median = (110 + 120) / 2
+        // q1 at rank 1.25 = 102.5, q3 at rank 3.75 = 127.5
+        let v = summarize(&[120.0, 3000.0, 90.0, 110.0, 130.0, 100.0]).unwrap();
+        assert_numerically_similar!(0.0001, v.median, 115.0);
+        assert_numerically_similar!(0.0001, v.iqr, 25.0);
 
-        kstr.add_key_event(KeyEvent { timestamp_ms: 12000, key: 'a' });
-        kstr.add_key_event(KeyEvent { timestamp_ms: 14000, key: 'b' });
+        let v = summarize(&[100.0, 200.0]).unwrap();
+        assert_numerically_similar!(0.0001, v.median, 150.0);
+        assert_numerically_similar!(0.0001, v.iqr, 50.0);
 
-        kstr.add_key_event(KeyEvent { timestamp_ms: 15000, key: 'a' });
-        kstr.add_key_event(KeyEvent { timestamp_ms: 18000, key: 'b' });
+        // a long pause moves the mean far more than the median
+        let profile = summarize(&[90.0, 100.0, 110.0, 100.0, 95.0, 105.0]).unwrap();
+        let sample = summarize(&[90.0, 100.0, 110.0, 100.0, 95.0, 5000.0]).unwrap();
+        let mut stats_profile = HashMap::new();
+        stats_profile.insert(('a', 'b'), profile);
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), sample);
 
-        // e-f digraphs, diffs = [500, 1000, 1500], mean = 1000.0, std = 500.0
-        // f-e digraphs, diffs = [500, 2000], mean = 1250.0, std = 1060.66
-        kstr.add_key_event(KeyEvent { timestamp_ms: 20000, key: 'e' });
-        kstr.add_key_event(KeyEvent { timestamp_ms: 20500, key: 'f' });
+        let mut diff_params = KeynomeAuthenticatorDiffParams::default();
+        assert!(KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params) > 500.0);
+        diff_params.use_median = true;
+        assert_numerically_similar!(0.0001,
+            KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params), 0.0);
 
-        kstr.add_key_event(KeyEvent { timestamp_ms: 21000, key: 'e' });
-        kstr.add_key_event(KeyEvent { timestamp_ms: 22000, key: 'f' });
+        // falls back to the means without quantiles on either side
+        stats_sample.insert(('a', 'b'), DigraphStats::new(6, 900.0, 2000.0));
+        assert_numerically_similar!(0.0001,
+            KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params), 800.0);
+    }
 
-        kstr.add_key_event(KeyEvent { timestamp_ms: 24000, key: 'e' });
-        kstr.add_key_event(KeyEvent { timestamp_ms: 25500, key: 'f' });
+    #[test]
+    fn keystroke_logger_digraph_statistics_trimmed() {
+        let mut kstr = KeystrokeLogger::new();
+        let mut ts = 10000;
+        // nine a-b digraphs of 90..130ms, then one after a 30 second pause
+        for (i, interval) in [90, 100, 110, 120, 130, 90, 100, 110, 120, 30000].iter().enumerate() {
+            kstr.add_key_event(KeyEvent::new(ts, 'a'));
+            ts += interval;
+            kstr.add_key_event(KeyEvent::new(ts, 'b'));
+            ts += 1000 + i as u128;
+            kstr.add_digraph_break();
+        }
 
         let stats = kstr.compute_digraph_statistics();
+        assert_eq!(stats[&('a', 'b')].size_samples, 10);
+        assert!(stats[&('a', 'b')].mean > 3000.0);
 
-        assert_numerically_similar!(0.01, stats[&('a', 'b')].mean, 2000.0);
-        assert_numerically_similar!(0.01, stats[&('a', 'b')].std, 1000.0);
-
-        assert_numerically_similar!(0.01, stats[&('b', 'a')].mean, 1000.0);
-        assert_numerically_similar!(0.01, stats[&('b', 'a')].std, 0.0);
+        let options = StatisticsOptions { max_interval_ms: Some(5000), ..Default::default() };
+        let stats = kstr.compute_digraph_statistics_with(&options);
+        assert_eq!(stats[&('a', 'b')].size_samples, 9);
+        assert_numerically_similar!(0.0001, stats[&('a', 'b')].mean, 970.0 / 9.0);
 
-        assert_numerically_similar!(0.01, stats[&('e', 'f')].mean, 1000.0);
-        assert_numerically_similar!(0.01, stats[&('e', 'f')].std, 500.0);
+        let options = StatisticsOptions { trim_sigma: Some(2.0), ..Default::default() };
+        let stats = kstr.compute_digraph_statistics_with(&options);
+        assert_eq!(stats[&('a', 'b')].size_samples, 9);
+        assert!(stats[&('a', 'b')].std < 20.0);
 
-        assert_numerically_similar!(0.01, stats[&('f', 'e')].mean, 1250.0);
-        assert_numerically_similar!(0.01, stats[&('f', 'e')].std, 1060.66);
+        assert_eq!(kstr.compute_digraph_statistics_with(&StatisticsOptions::default())[&('a', 'b')].size_samples, 10);
     }
 
     #[test]
-    fn keystroke_logger_digraph_statistics_serialization() {
-        let keystrokes = "The most likely way for the world to be destroyed, \
-                          most experts agree, is by accident.  That's where we come in \
-                          we're computer professionals.  We cause accidents. \
-                          - Nathaniel Borenstein";
+    fn keystroke_logger_incremental_statistics() {
+        let mut rng = SeededRng::new(7);
+        let mut batch = KeystrokeLogger::new();
+        batch.set_skip_post_correction(true);
+        let mut incremental = KeystrokeLogger::new();
+        incremental.set_skip_post_correction(true);
+        incremental.set_incremental(true);
 
-        let mut kstr = KeystrokeLogger::new();
-        for c in keystrokes.chars() {
-            kstr.add_keystroke(c);
+        // the buffer limit changes between phases, shrinking it evicts several events at once
+        let mut ts = 10000;
+        for _ in 0..4 {
+            let limit = 20 + (rng.next_u64() % 200) as usize;
+            batch.set_events_limit(limit);
+            incremental.set_events_limit(limit);
+            for _ in 0..500 {
+                ts += 50 + rng.next_u64() as u128 % 400;
+                let key = match rng.next_u64() % 10 {
+                    0 => BACKSPACE,
+                    r => (b'a' + (r % 4) as u8) as char,
+                };
+                if rng.next_f64() < 0.02 {
+                    batch.add_digraph_break();
+                    incremental.add_digraph_break();
+                }
+                batch.add_key_event(KeyEvent::new(ts, key));
+                incremental.add_key_event(KeyEvent::new(ts, key));
+
+                let expected = batch.compute_digraph_statistics();
+                let stats = incremental.compute_digraph_statistics();
+                assert_eq!(stats.len(), expected.len());
+                for (k, v) in expected.iter() {
+                    assert_eq!(stats[k].size_samples, v.size_samples);
+                    assert_numerically_similar!(0.000001, stats[k].mean, v.mean);
+                    assert_numerically_similar!(0.000001, stats[k].std, v.std);
+                    assert_eq!((stats[k].min, stats[k].max), (v.min, v.max));
+                }
+            }
         }
+    }
 
-        let stats = kstr.compute_digraph_statistics();
+    #[test]
+    fn user_profile_merge() {
+        let mut rng = SeededRng::new(11);
+        let mut first = KeystrokeLogger::new();
+        let mut second = KeystrokeLogger::new();
+        let mut whole = KeystrokeLogger::new();
+        let mut ts = 10000;
+        for i in 0..400 {
+            ts += 80 + rng.next_u64() as u128 % 200;
+            let key = "abcd".chars().nth(rng.next_u64() as usize % 4).unwrap();
+            if i == 200 {
+                whole.add_digraph_break();
+            }
+            if i < 200 { &mut first } else { &mut second }.add_key_event(KeyEvent::new(ts, key));
+            whole.add_key_event(KeyEvent::new(ts, key));
+        }
 
-        let serialized = KeystrokeLogger::serialize_digraph_statistics(&stats);
-        let deserialized = KeystrokeLogger::deserialize_digraph_statistics(&serialized);
+        let diff_params = KeynomeAuthenticatorDiffParams::default();
+        let mut profile_a = UserProfile::new(200, 50, 10.0, &diff_params, &first.compute_digraph_statistics());
+        profile_a.set_genuine_diffs(&[9.0, 11.0]);
+        let profile_b = UserProfile::new(600, 50, 14.0, &diff_params, &second.compute_digraph_statistics());
+        let merged = profile_a.merge(&profile_b).unwrap();
 
-        let mut keys_orig: Vec<String> = stats.keys().map(|d| format!("{}-{}", d.0, d.1)).collect();
-        let mut keys_new: Vec<String> = deserialized.keys().map(|d| format!("{}-{}", d.0, d.1)).collect();
+        assert_eq!(merged.n_profile, 800);
+        assert_numerically_similar!(0.0001, merged.diff_base, 13.0);
+        assert_eq!(merged.genuine_diffs, vec![9.0, 11.0]);
+        let expected = whole.compute_digraph_statistics();
+        assert_eq!(merged.stats.len(), expected.len());
+        for (k, v) in expected.iter() {
+            assert_eq!(merged.stats[k].size_samples, v.size_samples);
+            assert_numerically_similar!(0.000001, merged.stats[k].mean, v.mean);
+            assert_numerically_similar!(0.000001, merged.stats[k].std, v.std);
+        }
 
-        keys_orig.sort();
-        keys_new.sort();
-        assert_eq!(keys_orig, keys_new);
+        // with the raw intervals of both sessions the pooled statistics are the batch ones
+        let merged = profile_a.merge_with_samples(&profile_b, Some(&first.compute_digraph_samples()),
+                                                  Some(&second.compute_digraph_samples())).unwrap();
+        for (k, v) in expected.iter() {
+            assert_eq!(merged.stats[k].size_samples, v.size_samples);
+            assert_eq!(merged.stats[k].mean, v.mean);
+            assert_eq!(merged.stats[k].std, v.std);
+            assert_eq!(merged.stats[k].median, v.median);
+            assert_eq!(merged.stats[k].iqr, v.iqr);
+        }
+
+        let profile_c = UserProfile::new(200, 40, 10.0, &diff_params, &second.compute_digraph_statistics());
+        assert!(profile_a.merge(&profile_c).is_err());
     }
 
     #[test]
-    fn keynome_authenticator_compute_diff_base() {
-        let mut events = VecDeque::new();
-
-        // (Profile) For all 12 instances,
+    fn user_profile_adapt_converges() {
+        let mut stats = HashMap::new();
+        stats.insert(('a', 'b'), DigraphStats { median: 100.0, iqr: 13.0, ..DigraphStats::new(20, 100.0, 10.0) });
+        stats.insert(('x', 'y'), DigraphStats::new(20, 300.0, 30.0));
+        let mut profile = UserProfile::new(100, 50, 12.0, &KeynomeAuthenticatorDiffParams::default(), &stats);
 
-        // a-b digraphs
-        // diffs = [1000, 2000, 3000, 500, 1000, 1500]
-        // mean = 1500.0, std = 894.4272
+        // the user now types a-b at 150-170ms, and b-a for the first time
+        let mut rng = SeededRng::new(5);
+        let mut ts = 10000;
+        for _ in 0..60 {
+            let mut events = VecDeque::new();
+            for _ in 0..20 {
+                events.push_back(KeyEvent::new(ts, 'a'));
+                ts += 150 + rng.next_u64() as u128 % 21;
+                events.push_back(KeyEvent::new(ts, 'b'));
+                ts += 200;
+            }
+            profile.adapt(&events, 0.2);
+        }
 
-        // b-a digraphs
-        // diffs = [1000, 1000, 2000, 500, 2000]
-        // mean = 1300.0, std = 670.8204
+        let v = &profile.stats[&('a', 'b')];
+        assert!((v.mean - 160.0).abs() < 2.0);
+        assert!(v.std < 10.0);
+        assert!((v.median - 160.0).abs() < 3.0);
+        assert_numerically_similar!(0.0001, profile.stats[&('b', 'a')].mean, 200.0);
+        assert_numerically_similar!(0.0001, profile.stats[&('x', 'y')].mean, 300.0);
 
-        // (Sample 1) For first 6 instances,
+        // out-of-range rates are clamped rather than overshooting
+        let before = profile.stats[&('a', 'b')].mean;
+        let mut events = VecDeque::new();
+        for i in 0..4 {
+            events.push_back(KeyEvent::new(ts + 1000 * i, 'a'));
+            events.push_back(KeyEvent::new(ts + 1000 * i + 120, 'b'));
+        }
+        profile.adapt(&events, -1.0);
+        assert_numerically_similar!(0.0001, profile.stats[&('a', 'b')].mean, before);
+        profile.adapt(&events, 5.0);
+        assert_numerically_similar!(0.0001, profile.stats[&('a', 'b')].mean, 120.0);
+    }
 
-        // a-b digraphs
-        // diffs = [1000, 2000, 3000]
-        // mean = 2000.0, std = 1000.0
+    #[test]
+    fn digraph_stats_min_max() {
+        let mut kstr = KeystrokeLogger::new();
+        // a-b digraphs, diffs = [120, 80, 300, 95]
+        for (i, interval) in [120, 80, 300, 95].iter().enumerate() {
+            let ts = 10000 + 1000 * i as u128;
+            kstr.add_key_event(KeyEvent::new(ts, 'a'));
+            kstr.add_key_event(KeyEvent::new(ts + interval, 'b'));
+            kstr.add_digraph_break();
+        }
+        let v = &kstr.compute_digraph_statistics()[&('a', 'b')];
+        assert_numerically_similar!(0.0001, v.min, 80.0);
+        assert_numerically_similar!(0.0001, v.max, 300.0);
 
-        // b-a digraphs
-        // diffs = [1000, 1000]
-        // mean = 1000.0, std = 0
+        let mut running = RunningStats::new();
+        for x in [120.0, 80.0, 300.0, 95.0].iter() {
+            running.push(*x);
+        }
+        let w = running.to_stats().unwrap();
+        assert_numerically_similar!(0.0001, w.min, 80.0);
+        assert_numerically_similar!(0.0001, w.max, 300.0);
 
-        // (Sample 2) For last 6 instances,
+        let combined = v.combine(&summarize(&[60.0, 200.0]).unwrap());
+        assert_numerically_similar!(0.0001, combined.min, 60.0);
+        assert_numerically_similar!(0.0001, combined.max, 300.0);
 
-        // a-b digraphs
-        // diffs = [500, 1000, 1500]
-        // mean = 1000.0, std = 500.0
+        // serialized alongside mean/std, absent in older profiles
+        let roundtrip: DigraphStats = serde_json::from_str(&serde_json::to_string(v).unwrap()).unwrap();
+        assert_numerically_similar!(0.0001, roundtrip.max, 300.0);
+        let v1: DigraphStats = serde_json::from_str(r#"{"size_samples":4,"mean":100.0,"std":10.0}"#).unwrap();
+        assert!(!v1.has_range());
+        assert!(!v1.combine(v).has_range());
+    }
 
-        // b-a digraphs
-        // diffs = [500, 2000]
-        // mean = 1250.0, std = 1060.6602
+    #[test]
+    fn compute_diff_count_weighting() {
+        // a-b well sampled and close, c-d seen twice and far off
+        let mut stats_profile = HashMap::new();
+        stats_profile.insert(('a', 'b'), DigraphStats::new(200, 100.0, 10.0));
+        stats_profile.insert(('c', 'd'), DigraphStats::new(2, 100.0, 10.0));
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(98, 110.0, 10.0));
+        stats_sample.insert(('c', 'd'), DigraphStats::new(50, 300.0, 10.0));
 
-        events.push_back(KeyEvent { timestamp_ms: 10000, key: 'a' });
-        events.push_back(KeyEvent { timestamp_ms: 11000, key: 'b' });
+        let mut diff_params = KeynomeAuthenticatorDiffParams::default();
+        assert_numerically_similar!(0.0001,
+            KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params), 210.0);
 
-        events.push_back(KeyEvent { timestamp_ms: 12000, key: 'a' });
-        events.push_back(KeyEvent { timestamp_ms: 14000, key: 'b' });
+        // weights 98 and 2: (98 * 10 + 2 * 200) / 100
+        diff_params.count_weighting = true;
+        assert!(!diff_params.is_monotone());
+        assert_numerically_similar!(0.0001,
+            KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params), 13.8);
+    }
 
-        events.push_back(KeyEvent { timestamp_ms: 15000, key: 'a' });
-        events.push_back(KeyEvent { timestamp_ms: 18000, key: 'b' });
+    #[test]
+    fn compute_diff_distance_metrics() {
+        let mut stats_profile = HashMap::new();
+        stats_profile.insert(('a', 'b'), DigraphStats::new(11, 100.0, 10.0));
+        stats_profile.insert(('c', 'd'), DigraphStats::new(11, 200.0, 20.0));
+        let mut stats_sample = HashMap::new();
+        stats_sample.insert(('a', 'b'), DigraphStats::new(11, 130.0, 20.0));
+        stats_sample.insert(('c', 'd'), DigraphStats::new(11, 160.0, 20.0));
+        let diff = |metric: DistanceMetric| {
+            let diff_params = KeynomeAuthenticatorDiffParams { metric, ..Default::default() };
+            KeynomeAuthenticator::compute_diff(&stats_profile, &stats_sample, &diff_params)
+        };
 
-        events.push_back(KeyEvent { timestamp_ms: 20000, key: 'a' });
-        events.push_back(KeyEvent { timestamp_ms: 20500, key: 'b' });
+        assert_numerically_similar!(0.0001, diff(DistanceMetric::Absolute), 30.0 + 40.0);
+        assert_numerically_similar!(0.0001, diff(DistanceMetric::Squared), 900.0 + 1600.0);
+        // pooled stds sqrt((100 + 400) / 2) and 20
+        assert_numerically_similar!(0.0001, diff(DistanceMetric::PooledZScore), 30.0 / 250f64.sqrt() + 40.0 / 20.0);
+        // a-b: (3, 1) profile stds away, c-d: (2, 0)
+        assert_numerically_similar!(0.0001, diff(DistanceMetric::Mahalanobis), 10f64.sqrt() + 2.0);
+    }
 
-        events.push_back(KeyEvent { timestamp_ms: 21000, key: 'a' });
-        events.push_back(KeyEvent { timestamp_ms: 22000, key: 'b' });
+    #[test]
+    fn keystroke_logger_time_window() {
+        let mut kstr = KeystrokeLogger::new();
+        kstr.set_time_window(1000);
+        for i in 0..10 {
+            kstr.add_key_event(KeyEvent::new(10000 + 200 * i, 'a'));
+        }
+        // 11800 is the latest, so 10800 and later are kept
+        let events = kstr.get_key_events();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events.front().unwrap().timestamp_ms(), 10800);
 
-        events.push_back(KeyEvent { timestamp_ms: 24000, key: 'a' });
-        events.push_back(KeyEvent { timestamp_ms: 25500, key: 'b' });
+        // a long pause leaves only the new event
+        kstr.add_key_event(KeyEvent::new(20000, 'b'));
+        assert_eq!(kstr.get_key_events().len(), 1);
 
-        let diff_params = KeynomeAuthenticatorDiffParams {
-            dispersion: false,
-            min_instances: 1,
-            max_comparisons: 100,
-        };
+        // the event limit still applies when it is tighter
+        kstr.set_events_limit(3);
+        for i in 1..=5 {
+            kstr.add_key_event(KeyEvent::new(20000 + 10 * i, 'c'));
+        }
+        assert_eq!(kstr.get_key_events().len(), 3);
+        assert_eq!(kstr.get_key_events().front().unwrap().timestamp_ms(), 20030);
 
-        // Profile <=> Sample 1
-        // (a-b) abs(1500 - 2000) = 500
-        // (b-a) abs(1300 - 1000) = 300
-        // diff = 800
+        // narrowing the window trims the buffer right away
+        kstr.set_time_window(15);
+        assert_eq!(kstr.get_key_events().len(), 2);
 
-        // Profile <=> Sample 2
-        // (a-b) abs(1500 - 1000) = 500
-        // (b-a) abs(1300 - 1250) = 50
-        // diff = 550
+        // nothing is buffered with a limit of 0, which leaves the time window nothing to trim
+        kstr.set_events_limit(0);
+        kstr.add_key_event(KeyEvent::new(30000, 'd'));
+        assert!(kstr.get_key_events().is_empty());
+    }
 
-        // diff_base = (800 + 550) / 2 = 675
+    #[test]
+    fn keystroke_logger_set_events_limit_truncates() {
+        let mut kstr = KeystrokeLogger::new();
+        kstr.retain_evicted_digraphs(true);
+        for i in 0..500 {
+            kstr.add_key_event(KeyEvent::new(10000 + 100 * i, if i % 2 == 0 { 'a' } else { 'b' }));
+        }
+        kstr.set_events_limit(100);
+        assert_eq!(kstr.get_key_events().len(), 100);
+        assert_eq!(kstr.get_key_events().front().unwrap().timestamp_ms(), 10000 + 100 * 400);
+        // the truncated digraphs are retained like any other eviction
+        let stats = kstr.compute_digraph_statistics();
+        assert_eq!(stats[&('a', 'b')].size_samples + stats[&('b', 'a')].size_samples, 499);
 
-        let diff_base = KeynomeAuthenticator::compute_diff_base(&events, 12, 6, &diff_params).unwrap();
-        assert_eq!(diff_base, 675.0);
+        kstr.add_key_event(KeyEvent::new(70000, 'a'));
+        assert_eq!(kstr.get_key_events().len(), 100);
     }
 }
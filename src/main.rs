@@ -1,32 +1,177 @@
 use std::io::{stdin, Read};
 use std::io::prelude::*;
+use std::fs;
 use std::fs::File;
 use std::path::Path;
+use std::collections::{HashMap, VecDeque};
+use std::process;
+use std::env;
 
 extern crate clap;
 use clap::{Arg, App, AppSettings, SubCommand};
 
-mod lib;
-use lib::KeystrokeLogger;
-use lib::{KeynomeAuthenticator, KeynomeAuthenticatorDiffParams, UserProfile};
+use keynome::{estimate_clock_granularity_ms, stamp_last_updated, Digraph, KeyEvent, KeystrokeLogger};
+use keynome::{DistanceMetric, FrequencyWeighting, KeynomeAuthenticator, KeynomeError, KeynomeAuthenticatorDiffParams, ProfileMetadata, UserProfile};
+use keynome::{AcceptanceBands, AuthVerdict, ContinuousAuthenticator, EvaluationReport, RejectReason, WindowWeighting};
+use keynome::{SeededRng, WindowSizeTradeoff, COARSE_CLOCK_MS};
 
-fn save_user_profile(profile: &UserProfile, filename: &str) {
-    let serialized = profile.serialize();
+// profiles named *.gz are transparently gzip-compressed
+fn is_compressed(filename: &str) -> bool {
+    filename.ends_with(".gz")
+}
+
+// profiles are encrypted at rest when a passphrase is given in this environment variable
+const PASSPHRASE_VAR: &str = "KEYNOME_PASSPHRASE";
+
+fn profile_passphrase() -> Option<String> {
+    env::var(PASSPHRASE_VAR).ok().filter(|passphrase| !passphrase.is_empty())
+}
+
+fn save_user_profile(profile: &UserProfile, filename: &str, passphrase: Option<&str>) {
     let path = Path::new(filename);
-    let mut file = File::create(&path).unwrap();
-    file.write_all(serialized.as_bytes()).unwrap();
+    let mut file = File::create(path).unwrap();
+    if let Some(passphrase) = passphrase {
+        file.write_all(&profile.to_encrypted_bytes(passphrase).unwrap()).unwrap();
+    } else if is_compressed(filename) {
+        file.write_all(&profile.to_compressed_bytes()).unwrap();
+    } else {
+        file.write_all(profile.serialize().as_bytes()).unwrap();
+    }
     println!("user profile stored in {}.", filename);
 }
 
-fn load_user_profile(filename: &str) -> UserProfile {
+fn load_user_profile(filename: &str, passphrase: Option<&str>) -> UserProfile {
+    let path = Path::new(filename);
+    let mut file = File::open(path).unwrap();
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+
+    let loaded = if UserProfile::is_encrypted(&bytes) {
+        match passphrase {
+            Some(passphrase) => UserProfile::from_encrypted_bytes(&bytes, passphrase),
+            None => {
+                eprintln!("user profile {} is encrypted, set {} to its passphrase", filename, PASSPHRASE_VAR);
+                process::exit(1);
+            },
+        }
+    } else if is_compressed(filename) {
+        UserProfile::from_compressed_bytes(&bytes)
+    } else {
+        UserProfile::try_deserialize(&String::from_utf8_lossy(&bytes)).map_err(KeynomeError::from)
+    };
+    let profile = match loaded {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("cannot load user profile from {}: {}", filename, e);
+            process::exit(1);
+        },
+    };
+
+    if let Err(e) = profile.validate() {
+        eprintln!("cannot load user profile from {}: {}", filename, e);
+        process::exit(1);
+    }
+    profile
+}
+
+fn load_key_events(filename: &str) -> VecDeque<KeyEvent> {
     let path = Path::new(filename);
-    let mut file = File::open(&path).unwrap();
+    let mut file = File::open(path).unwrap();
 
     let mut s = String::new();
     file.read_to_string(&mut s).unwrap();
 
-    let profile = UserProfile::deserialize(&s);
-    profile
+    match KeystrokeLogger::deserialize_events_csv(&s) {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("cannot load key events from {}: {}", filename, e);
+            process::exit(1);
+        },
+    }
+}
+
+fn save_key_events(events: &VecDeque<KeyEvent>, filename: &str) {
+    let path = Path::new(filename);
+    let mut file = File::create(path).unwrap();
+    file.write_all(KeystrokeLogger::serialize_events_csv(events).as_bytes()).unwrap();
+    println!("key events stored in {}.", filename);
+}
+
+// every recorded session in a directory, in file name order
+fn load_key_event_dir(dirname: &str) -> Vec<VecDeque<KeyEvent>> {
+    let mut filenames: Vec<String> = fs::read_dir(dirname).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .map(|path| path.to_str().unwrap().to_string())
+        .collect();
+    filenames.sort();
+    filenames.iter().map(|filename| load_key_events(filename)).collect()
+}
+
+fn load_digraph_weights(filename: &str) -> HashMap<Digraph, f64> {
+    let path = Path::new(filename);
+    let mut file = File::open(path).unwrap();
+
+    let mut s = String::new();
+    file.read_to_string(&mut s).unwrap();
+
+    match KeynomeAuthenticatorDiffParams::try_parse_digraph_weights(&s) {
+        Ok(weights) => weights,
+        Err(e) => {
+            eprintln!("cannot load digraph weights from {}: {}", filename, e);
+            process::exit(1);
+        },
+    }
+}
+
+// the key a read character is logged as, None for ignored keys. With a shift normalizer the
+// character is pooled with its base key first, so shifted punctuation is kept along with letters
+fn tracked_key(ch: char, normalizer: Option<&HashMap<char, char>>) -> Option<char> {
+    let key = normalizer.and_then(|normalizer| normalizer.get(&ch)).copied().unwrap_or(ch);
+    let is_base_key = normalizer.is_some_and(|normalizer| normalizer.values().any(|base| *base == key));
+    if key.is_ascii_alphabetic() || is_base_key { Some(key) } else { None }
+}
+
+#[derive(Debug)]
+enum StopReason {
+    StopKey,
+    Eof,
+    NewlineFlood,
+}
+
+impl StopReason {
+    fn describe(&self) -> &'static str {
+        match self {
+            StopReason::StopKey => "'!' pressed",
+            StopReason::Eof => "end of input",
+            StopReason::NewlineFlood => "newline flood, too many consecutive newlines (terminal closed? see --newline_guard)",
+        }
+    }
+}
+
+// reads user keystrokes from Stdin character by character until '!' or EOF. When a
+// shelljacked terminal is closed, newlines are typed infinitely, so reading also stops
+// after more than `newline_guard` consecutive newlines (0 disables the guard)
+fn read_stdin_chars<F: FnMut(char)>(verbosity: u64, newline_guard: u32, mut on_char: F) -> StopReason {
+    let mut cnt_newline = 0;
+    let mut buf = [0];
+    while let Ok(1) = stdin().read(&mut buf) {
+        let ch = buf[0] as char;
+        if verbosity >= 1 {
+            println!("CHAR {:?}", ch);
+        }
+
+        if ch == '!' {
+            return StopReason::StopKey;
+        }
+        on_char(ch);
+
+        cnt_newline = if ch == '\n' { cnt_newline + 1 } else { 0 };
+        if newline_guard > 0 && cnt_newline > newline_guard {
+            return StopReason::NewlineFlood;
+        }
+    }
+    StopReason::Eof
 }
 
 fn main() {
@@ -61,6 +206,21 @@ fn main() {
                          .help("Sets the minimum number of digraph stats instances")
                          .default_value("2")
                          .takes_value(true))
+                    .arg(Arg::with_name("sample_min_instances")
+                         .long("sample_min_instances")
+                         .value_name("NUMBER")
+                         .help("Sets the minimum number of sample digraph stats instances (defaults to min_instances)")
+                         .takes_value(true))
+                    .arg(Arg::with_name("min_overlap_ratio")
+                         .long("min_overlap_ratio")
+                         .value_name("RATIO")
+                         .help("Sets the minimum fraction of profile digraphs a sample has to cover")
+                         .takes_value(true))
+                    .arg(Arg::with_name("top_n")
+                         .long("top_n")
+                         .value_name("NUMBER")
+                         .help("Sets the number of most sampled profile digraphs to compare, ignoring the rest")
+                         .takes_value(true))
                     .arg(Arg::with_name("max_comparisons")
                          .long("max_comparisons")
                          .value_name("NUMBER")
@@ -73,6 +233,144 @@ fn main() {
                          .help("Sets the flag for using dispersion when diff computed")
                          .default_value("0")
                          .takes_value(true))
+                    .arg(Arg::with_name("metric")
+                         .long("metric")
+                         .value_name("METRIC")
+                         .help("Sets the per-digraph distance used when diff computed")
+                         .possible_values(&["absolute", "scaled", "zscore", "squared", "pooled_zscore", "mahalanobis"])
+                         .default_value("absolute")
+                         .takes_value(true))
+                    .arg(Arg::with_name("stale_after_ms")
+                         .long("stale_after_ms")
+                         .value_name("MS")
+                         .help("Sets the age, relative to the most recently updated digraph, after which a digraph counts as stale")
+                         .takes_value(true))
+                    .arg(Arg::with_name("stale_weight")
+                         .long("stale_weight")
+                         .value_name("WEIGHT")
+                         .help("Sets the weight of stale digraphs when diff computed")
+                         .default_value("0.5")
+                         .takes_value(true))
+                    .arg(Arg::with_name("band_width")
+                         .long("band_width")
+                         .value_name("NUMBER")
+                         .help("Sets the width, in profile stds, of per-digraph acceptance bands used instead of the diff")
+                         .takes_value(true))
+                    .arg(Arg::with_name("band_min_fraction")
+                         .long("band_min_fraction")
+                         .value_name("RATIO")
+                         .help("Sets the fraction of compared digraphs that must fall within their band for acceptance")
+                         .default_value("0.8")
+                         .takes_value(true))
+                    .arg(Arg::with_name("tolerance_ms")
+                         .long("tolerance_ms")
+                         .value_name("MS")
+                         .help("Sets the digraph mean difference below which no diff is counted")
+                         .default_value("0")
+                         .takes_value(true))
+                    .arg(Arg::with_name("normalize_speed")
+                         .long("normalize_speed")
+                         .value_name("NUMBER")
+                         .help("Sets the flag for rescaling samples to the profile's typing speed when diff computed")
+                         .default_value("0")
+                         .takes_value(true))
+                    .arg(Arg::with_name("normalize_shift")
+                         .long("normalize_shift")
+                         .value_name("NUMBER")
+                         .help("Sets the flag for pooling shifted characters with their base key")
+                         .default_value("0")
+                         .takes_value(true))
+                    .arg(Arg::with_name("break_on_ignored")
+                         .long("break_on_ignored")
+                         .value_name("NUMBER")
+                         .help("Sets the flag for breaking digraphs at ignored (non-alphabetic) keys instead of joining their neighbors")
+                         .default_value("0")
+                         .takes_value(true))
+                    .arg(Arg::with_name("skip_windows")
+                         .long("skip_windows")
+                         .value_name("NUMBER")
+                         .help("Sets the number of first enrollment windows left out of diff_base")
+                         .default_value("0")
+                         .takes_value(true))
+                    .arg(Arg::with_name("recency_weighting")
+                         .long("recency_weighting")
+                         .value_name("NUMBER")
+                         .help("Sets the flag for weighting later windows more when diff_base computed")
+                         .default_value("0")
+                         .takes_value(true))
+                    .arg(Arg::with_name("weights")
+                         .long("weights")
+                         .value_name("FILE")
+                         .help("Sets a JSON file of per-digraph weights used when diff computed")
+                         .takes_value(true))
+                    .arg(Arg::with_name("frequency_weighting")
+                         .long("frequency_weighting")
+                         .value_name("SCHEME")
+                         .help("Sets the inverse-frequency weighting of digraphs: uniform, profile or table")
+                         .possible_values(&["uniform", "profile", "table"])
+                         .default_value("uniform")
+                         .requires_if("table", "frequency_table")
+                         .takes_value(true))
+                    .arg(Arg::with_name("frequency_table")
+                         .long("frequency_table")
+                         .value_name("FILE")
+                         .help("Sets a JSON file of relative digraph frequencies used by the table weighting")
+                         .takes_value(true))
+                    .arg(Arg::with_name("use_median")
+                         .long("use_median")
+                         .value_name("NUMBER")
+                         .help("Sets the flag for comparing digraph medians and IQRs instead of means and stds")
+                         .default_value("0")
+                         .takes_value(true))
+                    .arg(Arg::with_name("count_weighting")
+                         .long("count_weighting")
+                         .value_name("NUMBER")
+                         .help("Sets the flag for weighting digraphs by their sample counts when diff computed")
+                         .default_value("0")
+                         .takes_value(true))
+                    .arg(Arg::with_name("events")
+                         .long("events")
+                         .value_name("FILE")
+                         .help("Sets a CSV file (key,press_time,release_time) of recorded keystrokes to use instead of stdin")
+                         .takes_value(true))
+                    .arg(Arg::with_name("save_events")
+                         .long("save_events")
+                         .value_name("FILE")
+                         .help("Sets a CSV file where the captured keystrokes will be stored")
+                         .takes_value(true))
+                    .arg(Arg::with_name("max_digraphs")
+                         .long("max_digraphs")
+                         .value_name("NUMBER")
+                         .help("Sets the maximum number of digraphs stored, keeping the most sampled ones")
+                         .takes_value(true))
+                    .arg(Arg::with_name("label")
+                         .long("label")
+                         .value_name("LABEL")
+                         .help("Sets a user label stored in the profile metadata")
+                         .takes_value(true))
+                    .arg(Arg::with_name("newline_guard")
+                         .long("newline_guard")
+                         .value_name("NUMBER")
+                         .help("Sets the number of consecutive newlines after which reading stops, as when the terminal was closed (0 disables)")
+                         .default_value("10")
+                         .takes_value(true))
+                    .arg(Arg::with_name("freeze")
+                         .long("freeze")
+                         .value_name("NUMBER")
+                         .help("Sets the flag for freezing the profile, so that monitor never adapts it")
+                         .default_value("0")
+                         .takes_value(true))
+                    .arg(Arg::with_name("append")
+                         .long("append")
+                         .value_name("FILE")
+                         .help("Sets an existing user profile the new enrollment session is merged into")
+                         .takes_value(true))
+                    .arg(Arg::with_name("append_events")
+                         .long("append_events")
+                         .value_name("FILE")
+                         .help("Sets a CSV file of the keystrokes the appended profile was enrolled from, for an exact merge")
+                         .requires("append")
+                         .takes_value(true))
                     .arg(Arg::with_name("outfile")
                          .short("o")
                          .long("outfile")
@@ -89,10 +387,173 @@ fn main() {
                          .help("Sets an input file where a user profile is stored")
                          .required(true)
                          .takes_value(true))
+                    .arg(Arg::with_name("multiplier")
+                         .long("multiplier")
+                         .value_name("NUMBER")
+                         .help("Sets the multiplier of diff_base used as the rejection threshold")
+                         .default_value("1.5")
+                         .takes_value(true))
+                    .arg(Arg::with_name("newline_guard")
+                         .long("newline_guard")
+                         .value_name("NUMBER")
+                         .help("Sets the number of consecutive newlines after which reading stops, as when the terminal was closed (0 disables)")
+                         .default_value("10")
+                         .takes_value(true))
+        )
+        .subcommand(SubCommand::with_name("monitor")
+                    .about("authenticates continuously and adapts the user profile while accepted")
+                    .arg(Arg::with_name("infile")
+                         .short("i")
+                         .long("infile")
+                         .value_name("FILE")
+                         .help("Sets an input file where a user profile is stored, updated in place")
+                         .required(true)
+                         .takes_value(true))
+                    .arg(Arg::with_name("multiplier")
+                         .long("multiplier")
+                         .value_name("NUMBER")
+                         .help("Sets the multiplier of diff_base used as the rejection threshold")
+                         .default_value("1.5")
+                         .takes_value(true))
+                    .arg(Arg::with_name("learning_rate")
+                         .long("learning_rate")
+                         .value_name("RATE")
+                         .help("Sets the weight of a new window when the profile is updated")
+                         .default_value("0.05")
+                         .takes_value(true))
+                    .arg(Arg::with_name("adapt_after")
+                         .long("adapt_after")
+                         .value_name("NUMBER")
+                         .help("Sets the number of consecutive accepted keystrokes before adapting (defaults to n_sample)")
+                         .takes_value(true))
+                    .arg(Arg::with_name("save_every")
+                         .long("save_every")
+                         .value_name("NUMBER")
+                         .help("Sets the number of profile updates between saves")
+                         .default_value("10")
+                         .takes_value(true))
+                    .arg(Arg::with_name("newline_guard")
+                         .long("newline_guard")
+                         .value_name("NUMBER")
+                         .help("Sets the number of consecutive newlines after which reading stops, as when the terminal was closed (0 disables)")
+                         .default_value("10")
+                         .takes_value(true))
+                    .arg(Arg::with_name("track_comparisons")
+                         .long("track_comparisons")
+                         .value_name("NUMBER")
+                         .help("Sets the flag for reporting how often each digraph was compared when monitoring stops")
+                         .default_value("0")
+                         .takes_value(true))
+                    .arg(Arg::with_name("layout_change_ratio")
+                         .long("layout_change_ratio")
+                         .value_name("RATIO")
+                         .help("Sets the fraction of digraphs unknown to the profile above which a possible keyboard layout change is reported")
+                         .takes_value(true))
+                    .arg(Arg::with_name("forensic_log")
+                         .long("forensic_log")
+                         .value_name("FILE")
+                         .help("Sets a file where the statistics of each rejected window are appended")
+                         .takes_value(true))
+                    .arg(Arg::with_name("redact")
+                         .long("redact")
+                         .value_name("NUMBER")
+                         .help("Sets the flag for storing only timing, not typed content, in the forensic log")
+                         .default_value("1")
+                         .takes_value(true))
+        )
+        .subcommand(SubCommand::with_name("watch")
+                    .about("prints the running diff against a user profile while typing, without deciding")
+                    .arg(Arg::with_name("infile")
+                         .short("i")
+                         .long("infile")
+                         .value_name("FILE")
+                         .help("Sets an input file where a user profile is stored")
+                         .required(true)
+                         .takes_value(true))
+                    .arg(Arg::with_name("multiplier")
+                         .long("multiplier")
+                         .value_name("NUMBER")
+                         .help("Sets the multiplier of diff_base used as the shown threshold")
+                         .default_value("1.5")
+                         .takes_value(true))
+                    .arg(Arg::with_name("newline_guard")
+                         .long("newline_guard")
+                         .value_name("NUMBER")
+                         .help("Sets the number of consecutive newlines after which reading stops, as when the terminal was closed (0 disables)")
+                         .default_value("10")
+                         .takes_value(true))
+        )
+        .subcommand(SubCommand::with_name("evaluate")
+                    .about("measures FAR, FRR and EER of a user profile against recorded sessions")
+                    .arg(Arg::with_name("profile")
+                         .long("profile")
+                         .value_name("FILE")
+                         .help("Sets an input file where a user profile is stored")
+                         .required(true)
+                         .takes_value(true))
+                    .arg(Arg::with_name("genuine")
+                         .long("genuine")
+                         .value_name("DIR")
+                         .help("Sets a directory of CSV event files recorded by the profiled user")
+                         .required(true)
+                         .takes_value(true))
+                    .arg(Arg::with_name("impostors")
+                         .long("impostors")
+                         .value_name("DIR")
+                         .help("Sets a directory of CSV event files recorded by other users")
+                         .required(true)
+                         .takes_value(true))
+                    .arg(Arg::with_name("multiplier")
+                         .long("multiplier")
+                         .value_name("NUMBER")
+                         .help("Sets the multiplier of diff_base used as the rejection threshold")
+                         .default_value("1.5")
+                         .takes_value(true))
+        )
+        .subcommand(SubCommand::with_name("sweep")
+                    .about("compares decision latency and accuracy of n_sample values over a recorded session")
+                    .arg(Arg::with_name("events")
+                         .long("events")
+                         .value_name("FILE")
+                         .help("Sets a CSV file (key,press_time,release_time) of recorded keystrokes")
+                         .required(true)
+                         .takes_value(true))
+                    .arg(Arg::with_name("n_samples")
+                         .long("n_samples")
+                         .value_name("LIST")
+                         .help("Sets the comma-separated n_sample values to compare")
+                         .default_value("50,100,200,500,1000")
+                         .takes_value(true))
+                    .arg(Arg::with_name("seed")
+                         .long("seed")
+                         .value_name("NUMBER")
+                         .help("Sets the seed of the synthetic impostor")
+                         .takes_value(true))
+        )
+        .subcommand(SubCommand::with_name("info")
+                    .about("prints a compact summary of a user profile")
+                    .arg(Arg::with_name("infile")
+                         .short("i")
+                         .long("infile")
+                         .value_name("FILE")
+                         .help("Sets an input file where a user profile is stored")
+                         .required(true)
+                         .takes_value(true))
+        )
+        .subcommand(SubCommand::with_name("migrate")
+                    .about("rewrites a user profile of the legacy layout in the current one")
+                    .arg(Arg::with_name("infile")
+                         .short("i")
+                         .long("infile")
+                         .value_name("FILE")
+                         .help("Sets an input file where a user profile is stored, updated in place")
+                         .required(true)
+                         .takes_value(true))
         )
         .get_matches();
 
     let verbosity = matches.occurrences_of("verbosity");
+    let passphrase = profile_passphrase();
 
     // process subcommand
 
@@ -104,65 +565,387 @@ fn main() {
         let min_instances: u32 = matches.value_of("min_instances").unwrap().parse().unwrap();
         let max_comparisons: u32 = matches.value_of("max_comparisons").unwrap().parse().unwrap();
         let use_dispersion: u32 = matches.value_of("use_dispersion").unwrap().parse().unwrap();
+        let normalize_speed: u32 = matches.value_of("normalize_speed").unwrap().parse().unwrap();
+        let normalize_shift: u32 = matches.value_of("normalize_shift").unwrap().parse().unwrap();
+        let break_on_ignored: u32 = matches.value_of("break_on_ignored").unwrap().parse().unwrap();
+        let recency_weighting: u32 = matches.value_of("recency_weighting").unwrap().parse().unwrap();
+        let newline_guard: u32 = matches.value_of("newline_guard").unwrap().parse().unwrap();
+        let freeze: u32 = matches.value_of("freeze").unwrap().parse().unwrap();
 
-        println!("Press ! key to stop recording keystrokes");
-
+        let normalizer = if normalize_shift == 1 { Some(KeystrokeLogger::us_shift_normalizer()) } else { None };
         let mut kstr = KeystrokeLogger::new();
         kstr.set_events_limit(n_profile as usize);
+        if let Some(normalizer) = &normalizer {
+            kstr.set_key_normalizer(normalizer.clone());
+        }
 
-        // read user keystrokes from Stdin character by character
-        let mut cnt_newline = 0;
-        let mut buf = [0];
-        while let Ok(_) = stdin().read(&mut buf) {
-            let ch = buf[0] as char;
-            if verbosity >= 1 {
-                println!("CHAR {:?}", ch);
+        let stop_reason = if let Some(events_file) = matches.value_of("events") {
+            // replay recorded keystrokes instead of reading them live
+            for ev in load_key_events(events_file) {
+                kstr.add_key_event(ev);
             }
+            StopReason::Eof
+        } else {
+            println!("Press ! key to stop recording keystrokes");
 
-            if ch == '!' {
-                break;
-            } else if ch.is_ascii_alphabetic() {
-                kstr.add_keystroke(ch);
-            }
+            // keys tracked_key drops are ignored; by default their neighbors still form a digraph
+            read_stdin_chars(verbosity, newline_guard, |ch| {
+                if let Some(key) = tracked_key(ch, normalizer.as_ref()) {
+                    kstr.add_keystroke(key);
+                } else if break_on_ignored == 1 {
+                    kstr.add_digraph_break();
+                }
+            })
+        };
 
-            // if shelljacked-terminal is closed, newline is typed infinitely
-            cnt_newline = if ch == '\n' { cnt_newline + 1 } else { 0 };
-            if cnt_newline > 10 {
-                break;
-            }
+        if let Some(events_file) = matches.value_of("save_events") {
+            save_key_events(kstr.get_key_events(), events_file);
         }
 
         // compute statistics and serialize this
-        let stats = kstr.compute_digraph_statistics();
+        let mut stats = kstr.compute_digraph_statistics();
+        if let Some(ev) = kstr.get_key_events().back() {
+            stamp_last_updated(&mut stats, ev.timestamp_ms() as u64);
+        }
         if verbosity >= 2 {
             for (k, v) in stats.iter() {
-                println!("{:?}: mean({}), std({})", k, v.mean, v.std);
+                println!("{:?}: mean({}), std({}), min({}), max({})", k, v.mean, v.std, v.min, v.max);
             }
         }
 
         // compute inherent difference level
         let diff_params = KeynomeAuthenticatorDiffParams {
-            dispersion: if use_dispersion == 1 { true } else { false },
+            dispersion: use_dispersion == 1,
             min_instances,
             max_comparisons,
+            normalize_shift: normalize_shift == 1,
+            break_on_ignored: break_on_ignored == 1,
+            weights: matches.value_of("weights").map(load_digraph_weights),
+            sample_min_instances: matches.value_of("sample_min_instances").map(|v| v.parse().unwrap()),
+            min_overlap_ratio: matches.value_of("min_overlap_ratio").map(|v| v.parse().unwrap()),
+            top_n: matches.value_of("top_n").map(|v| v.parse().unwrap()),
+            normalize_speed: normalize_speed == 1,
+            tolerance_ms: matches.value_of("tolerance_ms").unwrap().parse().unwrap(),
+            metric: match matches.value_of("metric").unwrap() {
+                "scaled" => DistanceMetric::Scaled,
+                "zscore" => DistanceMetric::ZScore,
+                "squared" => DistanceMetric::Squared,
+                "pooled_zscore" => DistanceMetric::PooledZScore,
+                "mahalanobis" => DistanceMetric::Mahalanobis,
+                _ => DistanceMetric::Absolute,
+            },
+            stale_after_ms: matches.value_of("stale_after_ms").map(|v| v.parse().unwrap()),
+            stale_weight: matches.value_of("stale_weight").unwrap().parse().unwrap(),
+            skip_windows: matches.value_of("skip_windows").unwrap().parse().unwrap(),
+            frequency_weighting: match matches.value_of("frequency_weighting").unwrap() {
+                "profile" => FrequencyWeighting::ProfileCounts,
+                "table" => FrequencyWeighting::Table,
+                _ => FrequencyWeighting::Uniform,
+            },
+            frequency_table: matches.value_of("frequency_table").map(load_digraph_weights),
+            use_median: matches.value_of("use_median").unwrap().parse::<u32>().unwrap() == 1,
+            count_weighting: matches.value_of("count_weighting").unwrap().parse::<u32>().unwrap() == 1,
+            acceptance_bands: matches.value_of("band_width").map(|width| AcceptanceBands {
+                width: width.parse().unwrap(),
+                min_fraction: matches.value_of("band_min_fraction").unwrap().parse().unwrap(),
+            }),
         };
 
         let events = kstr.get_key_events();
-        let diff_base = KeynomeAuthenticator::compute_diff_base(events, 12, 6, &diff_params).unwrap();
+        let n_qualifying = stats.values().filter(|v| v.size_samples >= min_instances as usize).count();
+        // the diff base is calibrated on the digraphs the stored profile keeps
+        let max_digraphs: Option<usize> = matches.value_of("max_digraphs").map(|v| v.parse().unwrap());
+        let genuine_diffs = match max_digraphs {
+            Some(max_digraphs) => KeynomeAuthenticator::compute_diff_base_detailed_truncated(
+                events, n_profile as usize, n_sample as usize, &diff_params, max_digraphs),
+            None => KeynomeAuthenticator::compute_diff_base_detailed(
+                events, n_profile as usize, n_sample as usize, &diff_params),
+        };
+        println!("enrollment stopped: {}", stop_reason.describe());
+        println!("keystrokes captured: {} (of {} needed)", events.len(), n_profile);
+        println!("qualifying digraphs: {} (at least {} instances)", n_qualifying, min_instances);
+        if let Some(granularity) = estimate_clock_granularity_ms(events) {
+            if granularity > COARSE_CLOCK_MS {
+                println!("warning: timestamps have a granularity of {}ms, timing features may be unreliable", granularity);
+            }
+        }
+        println!("enrollment {}", if genuine_diffs.is_some() { "complete" } else { "incomplete, no profile stored" });
+
+        let genuine_diffs = match genuine_diffs {
+            Some(diffs) => diffs,
+            None => process::exit(1),
+        };
+        let weighting = if recency_weighting == 1 { WindowWeighting::Linear } else { WindowWeighting::Uniform };
+        let diff_base = weighting.weighted_mean(&genuine_diffs);
 
         // save a user profile
-        let profile = UserProfile::new(n_profile, n_sample, diff_base, &diff_params, &stats);
+        let mut profile = UserProfile::new(n_profile, n_sample, diff_base, &diff_params, &stats);
+        profile.set_genuine_diffs(&genuine_diffs);
+        profile.metadata = Some(ProfileMetadata::new(matches.value_of("label")));
+        if let Some(append) = matches.value_of("append") {
+            // without the earlier session's keystrokes its digraphs can only be pooled approximately
+            let earlier_samples = matches.value_of("append_events").map(|events_file| {
+                let mut earlier = KeystrokeLogger::new();
+                if let Some(normalizer) = &normalizer {
+                    earlier.set_key_normalizer(normalizer.clone());
+                }
+                for ev in load_key_events(events_file) {
+                    earlier.add_key_event(ev);
+                }
+                earlier.compute_digraph_samples()
+            });
+            let earlier = load_user_profile(append, passphrase.as_deref());
+            let samples = kstr.compute_digraph_samples();
+            profile = match profile.merge_with_samples(&earlier, Some(&samples), earlier_samples.as_ref()) {
+                Ok(merged) => merged,
+                Err(e) => {
+                    eprintln!("cannot append to user profile {}: {}", append, e);
+                    process::exit(1);
+                },
+            };
+        }
+        profile.frozen = freeze == 1;
+        if let Some(max_digraphs) = max_digraphs {
+            profile.truncate_digraphs(max_digraphs);
+        }
+        println!("overfit score: {:.2} (near 1.0 means enroll more data)", profile.overfit_score());
         let filename = matches.value_of("outfile").unwrap_or("profile.json");
-        save_user_profile(&profile, &filename);
+        save_user_profile(&profile, filename, passphrase.as_deref());
     }
 
     // Subcomnad - auth
     if let Some(matches) = matches.subcommand_matches("auth") {
         let filename = matches.value_of("infile").unwrap();
-        let profile = load_user_profile(&filename);
-        println!("n_profile: {}", profile.n_profile);
-        println!("n_sample: {}", profile.n_sample);
-        println!("diff_base: {}", profile.diff_base);
-        println!("diff_params: {:?}", profile.diff_params);
+        let multiplier: f64 = matches.value_of("multiplier").unwrap().parse().unwrap();
+        let newline_guard: u32 = matches.value_of("newline_guard").unwrap().parse().unwrap();
+
+        let profile = load_user_profile(filename, passphrase.as_deref());
+        if verbosity >= 1 {
+            println!("n_profile: {}", profile.n_profile);
+            println!("n_sample: {}", profile.n_sample);
+            println!("diff_base: {}", profile.diff_base);
+            println!("diff_params: {:?}", profile.diff_params);
+            println!("metadata: {:?}", profile.metadata);
+        }
+        let auth = profile.authenticator(multiplier);
+
+        // decide on a sliding window of the last n_sample keystrokes, pooling keys as when enrolled
+        let normalizer = if profile.diff_params.normalize_shift { Some(KeystrokeLogger::us_shift_normalizer()) } else { None };
+        let break_on_ignored = profile.diff_params.break_on_ignored;
+        let mut kstr = KeystrokeLogger::new();
+        kstr.set_events_limit(auth.n_sample);
+
+        println!("Press ! key to stop authentication");
+
+        let stop_reason = read_stdin_chars(verbosity, newline_guard, |ch| {
+            let key = match tracked_key(ch, normalizer.as_ref()) {
+                Some(key) => key,
+                None => {
+                    if break_on_ignored {
+                        kstr.add_digraph_break();
+                    }
+                    return;
+                },
+            };
+
+            kstr.add_keystroke(key);
+            if kstr.get_key_events().len() < auth.n_sample {
+                return;
+            }
+            let result = auth.authenticate_with_score(kstr.get_key_events());
+            println!("{} (diff: {:.2}, threshold: {:.2}, confidence: {:.2})",
+                     if result.accepted { "ACCEPT" } else { "REJECT" }, result.diff, result.threshold, result.confidence);
+        });
+
+        println!("authentication stopped: {}", stop_reason.describe());
+    }
+
+    // Subcomnad - monitor
+    if let Some(matches) = matches.subcommand_matches("monitor") {
+        let filename = matches.value_of("infile").unwrap();
+        let multiplier: f64 = matches.value_of("multiplier").unwrap().parse().unwrap();
+        let learning_rate: f64 = matches.value_of("learning_rate").unwrap().parse().unwrap();
+        let save_every: u32 = matches.value_of("save_every").unwrap().parse::<u32>().unwrap().max(1);
+        let redact: u32 = matches.value_of("redact").unwrap().parse().unwrap();
+        let newline_guard: u32 = matches.value_of("newline_guard").unwrap().parse().unwrap();
+        let mut forensic_log = matches.value_of("forensic_log").map(|filename| {
+            fs::OpenOptions::new().create(true).append(true).open(filename).unwrap()
+        });
+
+        let profile = load_user_profile(filename, passphrase.as_deref());
+        let adapt_after: u32 = matches.value_of("adapt_after")
+            .map(|v| v.parse().unwrap())
+            .unwrap_or(profile.n_sample);
+        if profile.frozen {
+            println!("user profile is frozen, it will not be adapted");
+        }
+        let normalizer = if profile.diff_params.normalize_shift { Some(KeystrokeLogger::us_shift_normalizer()) } else { None };
+        let break_on_ignored = profile.diff_params.break_on_ignored;
+        let mut auth = ContinuousAuthenticator::new(profile, multiplier);
+        auth.layout_change_ratio = matches.value_of("layout_change_ratio").map(|v| v.parse().unwrap());
+        let track_comparisons: u32 = matches.value_of("track_comparisons").unwrap().parse().unwrap();
+        auth.set_comparison_tracking(track_comparisons == 1);
+
+        println!("Press ! key to stop monitoring");
+
+        // adapt only on sustained acceptance, so that an impostor can't poison the profile
+        let mut last_verdict = AuthVerdict::Warming;
+        let mut n_accepted = 0;
+        let mut n_updates = 0;
+        let stop_reason = read_stdin_chars(verbosity, newline_guard, |ch| {
+            let key = match tracked_key(ch, normalizer.as_ref()) {
+                Some(key) => key,
+                None => {
+                    if break_on_ignored {
+                        auth.add_digraph_break();
+                    }
+                    return;
+                },
+            };
+
+            let verdict = auth.add_keystroke(key);
+            if verdict != last_verdict {
+                println!("{:?}", verdict);
+                if verdict == AuthVerdict::Reject(RejectReason::LayoutChanged) {
+                    println!("most typed digraphs are unknown, please check the keyboard layout");
+                }
+                last_verdict = verdict;
+            }
+            // every rejected window is recorded, not only the first of a run
+            if let AuthVerdict::Reject(_) = verdict {
+                if let Some(file) = &mut forensic_log {
+                    writeln!(file, "{}", auth.forensic_record(redact == 1)).unwrap();
+                }
+            }
+
+            n_accepted = if verdict == AuthVerdict::Accept { n_accepted + 1 } else { 0 };
+            if n_accepted >= adapt_after && !auth.profile.frozen {
+                let stats_window = auth.window_statistics();
+                auth.profile.update_with_sample(&stats_window, learning_rate);
+                n_accepted = 0;
+                n_updates += 1;
+                if n_updates % save_every == 0 {
+                    save_user_profile(&auth.profile, filename, passphrase.as_deref());
+                }
+            }
+        });
+
+        println!("monitoring stopped: {}", stop_reason.describe());
+
+        if let Some(counts) = auth.comparison_counts() {
+            let mut counts: Vec<(&Digraph, &u64)> = counts.iter().collect();
+            counts.sort_by(|(ka, na), (kb, nb)| nb.cmp(na).then(ka.cmp(kb)));
+            println!("digraphs compared: {}", counts.len());
+            for (k, n) in counts {
+                println!("  {}{}: {}", k.0, k.1, n);
+            }
+        }
+
+        // a short session should still get a decision, if only a tentative one
+        if last_verdict == AuthVerdict::Warming {
+            let (verdict, _) = auth.score_buffered();
+            println!("{:?} (partial window of {}/{} keystrokes, low confidence)",
+                     verdict, auth.get_key_events().len(), auth.profile.n_sample);
+        }
+
+        if n_updates % save_every != 0 {
+            save_user_profile(&auth.profile, filename, passphrase.as_deref());
+        }
+    }
+
+    // Subcomnad - watch
+    if let Some(matches) = matches.subcommand_matches("watch") {
+        let filename = matches.value_of("infile").unwrap();
+        let multiplier: f64 = matches.value_of("multiplier").unwrap().parse().unwrap();
+        let newline_guard: u32 = matches.value_of("newline_guard").unwrap().parse().unwrap();
+
+        let profile = load_user_profile(filename, passphrase.as_deref());
+        let normalizer = if profile.diff_params.normalize_shift { Some(KeystrokeLogger::us_shift_normalizer()) } else { None };
+        let break_on_ignored = profile.diff_params.break_on_ignored;
+        let mut auth = ContinuousAuthenticator::new(profile, multiplier);
+
+        println!("Press ! key to stop watching");
+
+        let stop_reason = read_stdin_chars(verbosity, newline_guard, |ch| {
+            let key = match tracked_key(ch, normalizer.as_ref()) {
+                Some(key) => key,
+                None => {
+                    if break_on_ignored {
+                        auth.add_digraph_break();
+                    }
+                    return;
+                },
+            };
+
+            // only the numbers are shown, the verdict is ignored
+            auth.add_keystroke(key);
+            let n_events = auth.get_key_events().len();
+            match auth.current_diff() {
+                Some(diff) => println!("diff: {:.2}, threshold: {:.2} ({}/{} keystrokes)",
+                                       diff, auth.threshold(), n_events, auth.profile.n_sample),
+                None => println!("diff: -, threshold: {:.2} ({}/{} keystrokes)",
+                                 auth.threshold(), n_events, auth.profile.n_sample),
+            }
+        });
+
+        println!("watching stopped: {}", stop_reason.describe());
+    }
+
+    // Subcomnad - evaluate
+    if let Some(matches) = matches.subcommand_matches("evaluate") {
+        let profile = load_user_profile(matches.value_of("profile").unwrap(), passphrase.as_deref());
+        let multiplier: f64 = matches.value_of("multiplier").unwrap().parse().unwrap();
+
+        let window_diffs = |dirname: &str| -> Vec<f64> {
+            load_key_event_dir(dirname).iter().flat_map(|events| profile.window_diffs(events)).collect()
+        };
+        let genuine_diffs = window_diffs(matches.value_of("genuine").unwrap());
+        let impostor_diffs = window_diffs(matches.value_of("impostors").unwrap());
+
+        match EvaluationReport::compute(&genuine_diffs, &impostor_diffs, profile.diff_base * multiplier) {
+            Some(report) => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+            None => {
+                eprintln!("no decidable window in the genuine or impostor sessions");
+                process::exit(1);
+            },
+        }
+    }
+
+    // Subcomnad - sweep
+    if let Some(matches) = matches.subcommand_matches("sweep") {
+        let events = load_key_events(matches.value_of("events").unwrap());
+        let n_samples: Vec<usize> = matches.value_of("n_samples").unwrap()
+            .split(',')
+            .map(|v| v.trim().parse().unwrap())
+            .collect();
+        let mut rng = SeededRng::from_seed(matches.value_of("seed").map(|v| v.parse().unwrap()));
+
+        let tradeoffs = WindowSizeTradeoff::sweep(&events, &n_samples, &KeynomeAuthenticatorDiffParams::default(), &mut rng);
+        println!("{:>8} {:>12} {:>12} {:>12} {:>12} {:>10}",
+                 "n_sample", "latency_ms", "genuine", "genuine_std", "impostor", "separation");
+        for t in tradeoffs {
+            println!("{:>8} {:>12.0} {:>12.2} {:>12.2} {:>12.2} {:>10.2}",
+                     t.n_sample, t.latency_ms, t.genuine_mean, t.genuine_std, t.impostor_mean, t.separation);
+        }
+    }
+
+    // Subcomnad - info
+    if let Some(matches) = matches.subcommand_matches("info") {
+        let filename = matches.value_of("infile").unwrap();
+        let profile = load_user_profile(filename, passphrase.as_deref());
+        println!("{}", profile.summary_json());
+    }
+
+    // Subcomnad - migrate
+    if let Some(matches) = matches.subcommand_matches("migrate") {
+        let filename = matches.value_of("infile").unwrap();
+        match UserProfile::migrate_legacy(filename) {
+            Ok(true) => println!("user profile migrated, the original is kept in {}.legacy", filename),
+            Ok(false) => println!("user profile is already in the current layout"),
+            Err(e) => {
+                eprintln!("cannot migrate user profile {}: {}", filename, e);
+                process::exit(1);
+            },
+        }
     }
 }